@@ -0,0 +1,289 @@
+/*!
+The Audio Processing Unit (APU) generates sound with four channels: two square waves, the first one with a
+frequency sweep, a wave channel playing samples from wave RAM and a noise channel. Each channel feeds a DAC,
+and the analog outputs are mixed into the left and right output according to NR51 and scaled by the master
+volume in NR50. The frame sequencer, clocked at 512 Hz by DIV, drives the length counters at 256 Hz, the
+sweep at 128 Hz and the volume envelopes at 64 Hz. The channels themselves run at the T-cycle rate of the
+normal speed mode, 4194304 Hz, and are resampled to the output rate with band-limited steps.
+https://gbdev.io/pandocs/Audio.html
+*/
+
+use crate::apu::noise::Noise;
+use crate::apu::square::Square;
+use crate::apu::stereo_buffer::StereoBuffer;
+use crate::apu::wave::Wave;
+use crate::model::Model;
+
+// The rate the channels are clocked at
+pub const CLOCK_RATE: u32 = 4_194_304;
+
+// Offsets of the registers inside the IO region
+const NR10: usize = 0x10;
+const NR50: usize = 0x24;
+const NR51: usize = 0x25;
+const NR52: usize = 0x26;
+const WAVE_RAM: usize = 0x30;
+
+const REGISTER_COUNT: usize = NR52 - NR10 + 1;
+
+// The bits of the registers NR10-NR52 that always read as 1, as they are write-only or unused
+const READ_MASKS: [u8; REGISTER_COUNT] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+pub const CHANNEL_COUNT: usize = 4;
+pub const CHANNEL_NAMES: [&str; CHANNEL_COUNT] = ["square1", "square2", "wave", "noise"];
+
+pub struct APU {
+    model: Model,
+    // NR52 bit 7, turning the APU off clears all registers
+    enabled: bool,
+    // The values last written to NR10-NR51
+    registers: [u8; REGISTER_COUNT],
+    // The step of the frame sequencer performed on its next clock
+    frame_step: u8,
+
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+
+    // No samples are produced until an output rate is set
+    sample_rate: Option<u32>,
+    output: Option<StereoBuffer>,
+    // The output of every channel on its own, while stems are recorded
+    stems: Option<[StereoBuffer; CHANNEL_COUNT]>,
+}
+
+impl APU {
+    pub fn new(model: Model) -> APU {
+        APU {
+            model,
+            enabled: false,
+            registers: [0; REGISTER_COUNT],
+            frame_step: 0,
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            sample_rate: None,
+            output: None,
+            stems: None,
+        }
+    }
+
+    // The boot ROM plays its sound on channel 1 and leaves the APU on with full volume on both outputs
+    pub fn skip_boot_rom(&mut self) {
+        self.write_register(NR52, 0x80);
+        self.write_register(NR50, 0x77);
+        self.write_register(NR51, 0xF3);
+        self.write_register(NR10 + 1, 0x80);
+        self.write_register(NR10 + 2, 0xF3);
+    }
+
+    // Starts producing samples at the given rate, such as 44100 or 48000 Hz, or stops with None
+    pub fn set_sample_rate(&mut self, sample_rate: Option<u32>) {
+        self.sample_rate = sample_rate;
+        self.output = sample_rate.map(|rate| StereoBuffer::new(self.model, CLOCK_RATE, rate));
+        if self.stems.is_some() {
+            self.set_stems_enabled(true);
+        }
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    // Produces the samples of every channel on its own as well, at the rate of the output
+    pub fn set_stems_enabled(&mut self, enabled: bool) {
+        self.stems = self
+            .sample_rate
+            .filter(|_| enabled)
+            .map(|rate| std::array::from_fn(|_| StereoBuffer::new(self.model, CLOCK_RATE, rate)));
+    }
+
+    // Returns the stereo samples completed since the last call, as interleaved left and right values
+    // between -1.0 and 1.0. The samples only depend on what the game did, so they can be compared
+    // against previously recorded ones
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        let mut samples = Vec::new();
+        if let Some(output) = self.output.as_mut() {
+            output.read_samples(&mut samples);
+        }
+        samples
+    }
+
+    // Returns the stereo samples of every channel completed since the last call, if stems are enabled
+    pub fn take_stem_samples(&mut self) -> Option<[Vec<f32>; CHANNEL_COUNT]> {
+        self.stems.as_mut().map(|stems| {
+            std::array::from_fn(|i| {
+                let mut samples = Vec::new();
+                stems[i].read_samples(&mut samples);
+                samples
+            })
+        })
+    }
+
+    // Reads the sound register or wave RAM at the given offset inside the IO region
+    pub fn read_register(&self, offset: usize) -> u8 {
+        match offset {
+            NR52 => {
+                let channels = [
+                    self.square1.enabled(),
+                    self.square2.enabled(),
+                    self.wave.enabled(),
+                    self.noise.enabled(),
+                ];
+                let status = channels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |status, (i, &enabled)| status | ((enabled as u8) << i));
+                READ_MASKS[NR52 - NR10] | ((self.enabled as u8) << 7) | status
+            }
+            NR10..NR52 => self.registers[offset - NR10] | READ_MASKS[offset - NR10],
+            WAVE_RAM.. => self.wave.read_ram(offset - WAVE_RAM),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, offset: usize, value: u8) {
+        match offset {
+            NR52 => {
+                let enabled = (value & 0x80) == 0x80;
+                if self.enabled && !enabled {
+                    self.turn_off();
+                } else if !self.enabled && enabled {
+                    self.frame_step = 0;
+                }
+                self.enabled = enabled;
+            }
+            // While the APU is off, only NR52 and wave RAM can be written
+            NR10..NR52 if self.enabled => {
+                self.registers[offset - NR10] = value;
+
+                // Every channel has 5 registers, starting at NR10, NR20, NR30 and NR40
+                let next_step_clocks_length = self.frame_step.is_multiple_of(2);
+                let register = (offset - NR10) % 5;
+                match (offset - NR10) / 5 {
+                    0 => self.square1.write(register, value, next_step_clocks_length),
+                    1 => self.square2.write(register, value, next_step_clocks_length),
+                    2 => self.wave.write(register, value, next_step_clocks_length),
+                    3 => self.noise.write(register, value, next_step_clocks_length),
+                    _ => {}
+                }
+            }
+            WAVE_RAM.. => self.wave.write_ram(offset - WAVE_RAM, value),
+            _ => {}
+        }
+    }
+
+    // Turning the APU off resets all channels and clears all registers, except for wave RAM
+    fn turn_off(&mut self) {
+        self.registers = [0; REGISTER_COUNT];
+        self.square1 = Square::new(true);
+        self.square2 = Square::new(false);
+        self.wave.reset();
+        self.noise = Noise::new();
+    }
+
+    // Performs the next step of the frame sequencer, on the falling edge of DIV bit 4, or bit 5 in double
+    // speed mode
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.frame_step.is_multiple_of(2) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    // Advances the channels by one T-cycle at normal speed
+    pub fn tick(&mut self) {
+        if self.enabled {
+            self.square1.tick();
+            self.square2.tick();
+            self.wave.tick();
+            self.noise.tick();
+        }
+
+        if self.output.is_some() {
+            let channels = self.mix();
+            let (left, right) = channels
+                .iter()
+                .fold((0.0, 0.0), |(left, right), (l, r)| (left + l, right + r));
+            if let Some(output) = self.output.as_mut() {
+                output.tick(left, right);
+            }
+            if let Some(stems) = self.stems.as_mut() {
+                for (stem, (left, right)) in stems.iter_mut().zip(channels) {
+                    stem.tick(left, right);
+                }
+            }
+        }
+    }
+
+    // The analog output of every channel's DAC, from -1.0 to 1.0. A DAC that is off outputs 0.0
+    fn channel_outputs(&self) -> [f32; CHANNEL_COUNT] {
+        let dac = |enabled: bool, output: u8| {
+            if enabled {
+                output as f32 / 7.5 - 1.0
+            } else {
+                0.0
+            }
+        };
+
+        [
+            dac(self.square1.dac_enabled(), self.square1.output()),
+            dac(self.square2.dac_enabled(), self.square2.output()),
+            dac(self.wave.dac_enabled(), self.wave.output()),
+            dac(self.noise.dac_enabled(), self.noise.output()),
+        ]
+    }
+
+    // The part every channel contributes to the left and right output, according to NR51 and NR50
+    fn mix(&self) -> [(f32, f32); CHANNEL_COUNT] {
+        if !self.enabled {
+            return [(0.0, 0.0); CHANNEL_COUNT];
+        }
+
+        let nr50 = self.registers[NR50 - NR10];
+        let nr51 = self.registers[NR51 - NR10];
+
+        // The master volume scales from 1/8 to 8/8, the sum of the channels is brought back into range
+        let left_volume = (((nr50 >> 4) & 0x07) + 1) as f32 / 8.0 / CHANNEL_COUNT as f32;
+        let right_volume = ((nr50 & 0x07) + 1) as f32 / 8.0 / CHANNEL_COUNT as f32;
+
+        let outputs = self.channel_outputs();
+        std::array::from_fn(|i| {
+            let left = if (nr51 >> (i + 4)) & 0x01 == 0x01 {
+                outputs[i] * left_volume
+            } else {
+                0.0
+            };
+            let right = if (nr51 >> i) & 0x01 == 0x01 {
+                outputs[i] * right_volume
+            } else {
+                0.0
+            };
+            (left, right)
+        })
+    }
+}
@@ -0,0 +1,111 @@
+/*!
+A band-limited step buffer resamples a signal that only changes in steps at the 4194304 Hz clock rate to a
+much lower output rate without aliasing. Instead of point-sampling the signal, every step is added as a
+band-limited step, whose differences are spread over a few output samples around the exact time of the
+step. Summing up the differences again yields the output samples, delayed by half the width of the kernel.
+Times are tracked in whole clocks and the kernel is quantized to a fixed number of phases, so the output
+only depends on the input.
+http://www.slack.net/~ant/bl-synth/
+*/
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// The output samples a step is spread over
+const KERNEL_WIDTH: usize = 16;
+// The resolution of the time of a step between two output samples
+const PHASE_COUNT: usize = 32;
+// The cutoff frequency of the kernel relative to the Nyquist frequency of the output rate, leaving room
+// for the transition band of the window
+const CUTOFF: f64 = 0.9;
+
+pub struct BlipBuffer {
+    clock_rate: u64,
+    sample_rate: u64,
+    kernel: Box<[[f32; KERNEL_WIDTH]; PHASE_COUNT]>,
+
+    // The clocks since the buffer was created
+    clock: u64,
+    // The output sample the first difference belongs to. Output samples are counted from half the width of
+    // the kernel before the first clock, so the kernel of a step at the first clock fits in
+    first_sample: u64,
+    differences: VecDeque<f32>,
+    // The sum of all differences taken out so far, which is the value of the last output sample
+    sum: f32,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> BlipBuffer {
+        BlipBuffer {
+            clock_rate: clock_rate as u64,
+            sample_rate: sample_rate as u64,
+            kernel: BlipBuffer::kernel(),
+            clock: 0,
+            first_sample: 0,
+            differences: VecDeque::new(),
+            sum: 0.0,
+        }
+    }
+
+    // The differences of a band-limited step at every phase, a windowed sinc that sums up to 1
+    fn kernel() -> Box<[[f32; KERNEL_WIDTH]; PHASE_COUNT]> {
+        let mut kernel = Box::new([[0.0; KERNEL_WIDTH]; PHASE_COUNT]);
+
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let mut values = [0.0; KERNEL_WIDTH];
+            for (i, value) in values.iter_mut().enumerate() {
+                // The distance of the center of the sample from the step
+                let x =
+                    i as f64 - (KERNEL_WIDTH / 2) as f64 + 0.5 - phase as f64 / PHASE_COUNT as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * CUTOFF * x).sin() / (PI * CUTOFF * x)
+                };
+                // A Blackman window over the width of the kernel
+                let position = (x / KERNEL_WIDTH as f64 + 0.5).clamp(0.0, 1.0);
+                let window =
+                    0.42 - 0.5 * (2.0 * PI * position).cos() + 0.08 * (4.0 * PI * position).cos();
+                *value = sinc * window;
+            }
+
+            let total: f64 = values.iter().sum();
+            for (tap, value) in taps.iter_mut().zip(values) {
+                *tap = (value / total) as f32;
+            }
+        }
+        kernel
+    }
+
+    // Adds a step of the given height at the current clock
+    pub fn add_step(&mut self, delta: f32) {
+        let position = self.clock * self.sample_rate;
+        let sample = position / self.clock_rate;
+        let phase = ((position % self.clock_rate) * PHASE_COUNT as u64 / self.clock_rate) as usize;
+
+        // The kernel is centered on the step, which is half its width after the first sample it covers
+        let start = sample + 1;
+        let offset = (start - self.first_sample) as usize;
+        if self.differences.len() < offset + KERNEL_WIDTH {
+            self.differences.resize(offset + KERNEL_WIDTH, 0.0);
+        }
+        for (i, tap) in self.kernel[phase].iter().enumerate() {
+            self.differences[offset + i] += delta * tap;
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.clock += 1;
+    }
+
+    // Appends the output samples that no future step can change anymore
+    pub fn read_samples(&mut self, samples: &mut Vec<f32>) {
+        let current_sample = self.clock * self.sample_rate / self.clock_rate;
+
+        while self.first_sample <= current_sample {
+            self.sum += self.differences.pop_front().unwrap_or(0.0);
+            samples.push(self.sum);
+            self.first_sample += 1;
+        }
+    }
+}
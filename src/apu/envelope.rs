@@ -0,0 +1,62 @@
+/*!
+The volume envelope of the square and noise channels, controlled by NRx2. Every pace ticks of the 64 Hz
+envelope clock of the frame sequencer, the volume is increased or decreased by one until it reaches 15 or 0.
+A pace of 0 leaves the volume unchanged.
+https://gbdev.io/pandocs/Audio_Registers.html#ff12--nr12-channel-1-volume--envelope
+*/
+
+pub struct Envelope {
+    // NRx2
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    // The DAC of the channel is only powered while the upper 5 bits of NRx2 are not all 0
+    pub fn dac_enabled(&self) -> bool {
+        (self.register & 0xF8) != 0
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.pace();
+    }
+
+    pub fn clock(&mut self) {
+        if self.pace() == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.pace();
+            let increase = (self.register & 0x08) == 0x08;
+            if increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    fn pace(&self) -> u8 {
+        self.register & 0x07
+    }
+}
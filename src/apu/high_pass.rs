@@ -0,0 +1,76 @@
+/*!
+The outputs of the APU pass through a capacitor that blocks DC, such as the offset of a DAC that is on but
+silent. It acts as a high-pass filter, whose capacitor charges by a fixed factor per clock. The CGB charges
+it much faster than the DMG, which cuts off more of the low frequencies.
+https://gbdev.io/pandocs/Audio_details.html#obscure-behavior
+*/
+
+use crate::model::Model;
+
+// The fraction of the charge the capacitor keeps per clock at 4194304 Hz
+const DMG_CHARGE_FACTOR: f64 = 0.999958;
+const CGB_CHARGE_FACTOR: f64 = 0.998943;
+
+pub struct HighPassFilter {
+    // The charge factor per output sample
+    factor: f32,
+    capacitor: f32,
+}
+
+impl HighPassFilter {
+    pub fn new(model: Model, clock_rate: u32, sample_rate: u32) -> HighPassFilter {
+        let charge_factor = match model {
+            Model::DMG => DMG_CHARGE_FACTOR,
+            Model::CGB => CGB_CHARGE_FACTOR,
+        };
+
+        HighPassFilter {
+            factor: charge_factor.powf(clock_rate as f64 / sample_rate as f64) as f32,
+            capacitor: 0.0,
+        }
+    }
+
+    pub fn apply(&mut self, input: f32) -> f32 {
+        let output = input - self.capacitor;
+        self.capacitor = input - output * self.factor;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_dc() {
+        for model in [Model::DMG, Model::CGB] {
+            let mut filter = HighPassFilter::new(model, 4194304, 48000);
+
+            // A step passes right away, but a constant offset decays within a second
+            assert_eq!(filter.apply(1.0), 1.0);
+            let mut output = 1.0;
+            for _ in 0..48000 {
+                output = filter.apply(1.0);
+            }
+            assert!(
+                output.abs() < 1e-3,
+                "{model:?} keeps {output} of the offset"
+            );
+        }
+    }
+
+    #[test]
+    fn passes_audible_frequencies() {
+        // A 1 kHz square wave keeps most of its amplitude
+        let mut filter = HighPassFilter::new(Model::DMG, 4194304, 48000);
+        let mut peak: f32 = 0.0;
+        for i in 0..48000 {
+            let input = if (i / 24) % 2 == 0 { 1.0 } else { -1.0 };
+            let output = filter.apply(input);
+            if i >= 47000 {
+                peak = peak.max(output.abs());
+            }
+        }
+        assert!(peak > 0.9, "The peak is {peak}");
+    }
+}
@@ -0,0 +1,55 @@
+/*!
+The length counter turns a channel off after a set time. Writing NRx1 loads it with 64 or 256 minus the
+given length, and while enabled in NRx4, the frame sequencer decrements it at 256 Hz until it reaches 0.
+https://gbdev.io/pandocs/Audio_details.html#length-timer
+*/
+
+pub struct LengthCounter {
+    // 64 for the square and noise channels, 256 for the wave channel
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    // Loads the counter from the length written to NRx1
+    pub fn load(&mut self, length: u16) {
+        self.counter = self.max - length;
+    }
+
+    // Enabling the counter in the half of the frame sequencer period that does not clock it clocks it once
+    // more. Returns whether this expired the counter
+    pub fn set_enabled(&mut self, enabled: bool, next_step_clocks: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enabled;
+        !was_enabled && enabled && !next_step_clocks && self.clock()
+    }
+
+    // A triggered channel with an expired counter plays for the full length
+    pub fn trigger(&mut self, next_step_clocks: bool) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && !next_step_clocks {
+                self.counter -= 1;
+            }
+        }
+    }
+
+    // Decrements the counter and returns whether it just expired, which turns the channel off
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,15 @@
+mod apu;
+mod blip_buffer;
+mod envelope;
+mod high_pass;
+mod length_counter;
+mod noise;
+mod recorder;
+mod square;
+mod stereo_buffer;
+mod sweep;
+mod wav_writer;
+mod wave;
+
+pub use apu::APU;
+pub use recorder::Recorder;
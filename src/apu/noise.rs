@@ -0,0 +1,125 @@
+/*!
+The noise channel outputs the lowest bit of a 15-bit Linear Feedback Shift Register (LFSR). On every clock,
+the XNOR of its two lowest bits is shifted in from the top, and in 7-bit mode also into bit 6, which makes
+the noise repeat every 127 clocks and sound more tonal. NR43 selects the clock from a divider and shift.
+https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-4--noise
+*/
+
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+// The clock divider selected by NR43 bits 0-2, in T-cycles
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+pub struct Noise {
+    length: LengthCounter,
+    envelope: Envelope,
+    enabled: bool,
+
+    // NR43
+    register: u8,
+    lfsr: u16,
+    // The T-cycles until the next clock of the LFSR
+    timer: u32,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            enabled: false,
+            register: 0,
+            lfsr: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    // Writes NR41-NR44, given by their index. Whether the next step of the frame sequencer clocks the length
+    // counter decides how enabling the length counter and triggering behave
+    pub fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {}
+            1 => self.length.load((value & 0x3F) as u16),
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.register = value,
+            _ => {
+                let trigger = (value & 0x80) == 0x80;
+                let expired = self
+                    .length
+                    .set_enabled((value & 0x40) == 0x40, next_step_clocks_length);
+                if expired && !trigger {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.enabled = self.envelope.dac_enabled();
+                    self.length.trigger(next_step_clocks_length);
+                    self.envelope.trigger();
+                    self.timer = self.reload_value();
+                    self.lfsr = 0;
+                }
+            }
+        }
+    }
+
+    // Advances the channel by one T-cycle
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.reload_value();
+
+        // Shifts of 14 and 15 stop the LFSR
+        if self.shift() >= 14 {
+            return;
+        }
+
+        let bit = !(self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+        self.lfsr = (self.lfsr & 0x7FFF) | (bit << 15);
+        if (self.register & 0x08) == 0x08 {
+            self.lfsr = (self.lfsr & !0x80) | (bit << 7);
+        }
+        self.lfsr >>= 1;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // The digital output 0-15 fed into the DAC
+    pub fn output(&self) -> u8 {
+        if self.enabled && (self.lfsr & 0x01) == 0x01 {
+            self.envelope.volume()
+        } else {
+            0
+        }
+    }
+
+    fn shift(&self) -> u8 {
+        self.register >> 4
+    }
+
+    fn reload_value(&self) -> u32 {
+        DIVISORS[(self.register & 0x07) as usize] << self.shift()
+    }
+}
@@ -0,0 +1,62 @@
+/*!
+The recorder writes the stereo output of the APU to a WAV file, and optionally the part every channel
+contributes to it to a separate file per channel. These stems are mixed with the same panning and master
+volume as the output, so adding them up gives the output.
+*/
+
+use crate::apu::apu::{CHANNEL_COUNT, CHANNEL_NAMES};
+use crate::apu::wav_writer::WavWriter;
+use std::path::Path;
+
+const STEREO: u16 = 2;
+
+pub struct Recorder {
+    output: WavWriter,
+    stems: Option<Vec<WavWriter>>,
+}
+
+impl Recorder {
+    // The stems are written next to the output file, with the name of the channel added before the
+    // extension, such as music.square1.wav for music.wav
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        sample_rate: u32,
+        stems: bool,
+    ) -> std::io::Result<Recorder> {
+        let path = path.as_ref();
+        let output = WavWriter::create(path, STEREO, sample_rate)?;
+        let stems = if stems {
+            let writers = CHANNEL_NAMES
+                .iter()
+                .map(|name| {
+                    let stem_path = path.with_extension(format!("{name}.wav"));
+                    WavWriter::create(stem_path, STEREO, sample_rate)
+                })
+                .collect::<std::io::Result<Vec<_>>>()?;
+            Some(writers)
+        } else {
+            None
+        };
+
+        Ok(Recorder { output, stems })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.output.path()
+    }
+
+    // Appends interleaved stereo samples of the output and, if recorded, of every channel
+    pub fn write(
+        &mut self,
+        samples: &[f32],
+        stem_samples: Option<&[Vec<f32>; CHANNEL_COUNT]>,
+    ) -> std::io::Result<()> {
+        self.output.write_samples(samples)?;
+        if let (Some(stems), Some(stem_samples)) = (self.stems.as_mut(), stem_samples) {
+            for (stem, samples) in stems.iter_mut().zip(stem_samples) {
+                stem.write_samples(samples)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+/*!
+The square channels 1 and 2 play a square wave with one of four duty cycles. The period register holds 2048
+minus the length of one of the 8 steps of the wave in units of 4 T-cycles. Only channel 1 has a sweep.
+https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-1--pulse-with-period-sweep
+*/
+
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::sweep::Sweep;
+
+// The 8 steps of the 12.5%, 25%, 50% and 75% duty cycles
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+pub struct Square {
+    sweep: Option<Sweep>,
+    length: LengthCounter,
+    envelope: Envelope,
+    enabled: bool,
+
+    duty: u8,
+    duty_step: u8,
+    period: u16,
+    // The T-cycles until the next step of the wave
+    timer: u16,
+}
+
+impl Square {
+    pub fn new(with_sweep: bool) -> Square {
+        Square {
+            sweep: with_sweep.then(Sweep::new),
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            period: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    // Writes NRx0-NRx4, given by their index. Whether the next step of the frame sequencer clocks the length
+    // counter decides how enabling the length counter and triggering behave
+    pub fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {
+                if let Some(sweep) = self.sweep.as_mut()
+                    && sweep.write(value)
+                {
+                    self.enabled = false;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load((value & 0x3F) as u16);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.period = (self.period & 0x700) | value as u16,
+            _ => {
+                self.period = (self.period & 0xFF) | ((value as u16 & 0x07) << 8);
+                let trigger = (value & 0x80) == 0x80;
+                let expired = self
+                    .length
+                    .set_enabled((value & 0x40) == 0x40, next_step_clocks_length);
+                if expired && !trigger {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger(next_step_clocks_length);
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self, next_step_clocks_length: bool) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger(next_step_clocks_length);
+        self.envelope.trigger();
+        self.timer = self.reload_value();
+
+        if let Some(sweep) = self.sweep.as_mut()
+            && sweep.trigger(self.period)
+        {
+            self.enabled = false;
+        }
+    }
+
+    // Advances the channel by one T-cycle
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.reload_value();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if let Some(sweep) = self.sweep.as_mut() {
+            let (period, overflow) = sweep.clock();
+            if let Some(period) = period {
+                self.period = period;
+            }
+            if overflow {
+                self.enabled = false;
+            }
+        }
+    }
+
+    // The digital output 0-15 fed into the DAC
+    pub fn output(&self) -> u8 {
+        if self.enabled {
+            DUTY_CYCLES[self.duty as usize][self.duty_step as usize] * self.envelope.volume()
+        } else {
+            0
+        }
+    }
+
+    fn reload_value(&self) -> u16 {
+        (2048 - self.period) * 4
+    }
+}
@@ -0,0 +1,113 @@
+/*!
+The stereo buffer turns the left and right amplitude of the APU at the 4194304 Hz clock rate into filtered
+samples at the output rate, using a band-limited step buffer and a high-pass filter for each side.
+*/
+
+use crate::apu::blip_buffer::BlipBuffer;
+use crate::apu::high_pass::HighPassFilter;
+use crate::model::Model;
+
+pub struct StereoBuffer {
+    left: BlipBuffer,
+    right: BlipBuffer,
+    left_filter: HighPassFilter,
+    right_filter: HighPassFilter,
+
+    // The amplitudes at the last clock
+    left_amplitude: f32,
+    right_amplitude: f32,
+}
+
+impl StereoBuffer {
+    pub fn new(model: Model, clock_rate: u32, sample_rate: u32) -> StereoBuffer {
+        StereoBuffer {
+            left: BlipBuffer::new(clock_rate, sample_rate),
+            right: BlipBuffer::new(clock_rate, sample_rate),
+            left_filter: HighPassFilter::new(model, clock_rate, sample_rate),
+            right_filter: HighPassFilter::new(model, clock_rate, sample_rate),
+            left_amplitude: 0.0,
+            right_amplitude: 0.0,
+        }
+    }
+
+    // Advances by one clock, with the amplitudes of both sides during it
+    pub fn tick(&mut self, left: f32, right: f32) {
+        if left != self.left_amplitude {
+            self.left.add_step(left - self.left_amplitude);
+            self.left_amplitude = left;
+        }
+        if right != self.right_amplitude {
+            self.right.add_step(right - self.right_amplitude);
+            self.right_amplitude = right;
+        }
+        self.left.tick();
+        self.right.tick();
+    }
+
+    // Appends the completed samples as interleaved left and right values
+    pub fn read_samples(&mut self, samples: &mut Vec<f32>) {
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        self.left.read_samples(&mut left);
+        self.right.read_samples(&mut right);
+
+        for (left, right) in left.into_iter().zip(right) {
+            samples.push(self.left_filter.apply(left));
+            samples.push(self.right_filter.apply(right));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOLDEN_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/apu/testdata/stereo_buffer.txt"
+    );
+
+    // A 1 kHz square wave on the left and a staircase of uneven steps at uneven times on the right, as
+    // left and right samples, one stereo sample per line
+    fn render() -> String {
+        let mut buffer = StereoBuffer::new(Model::DMG, 4194304, 48000);
+        let mut samples = Vec::new();
+        for clock in 0..4194304 / 100 {
+            let left = if (clock / 2097) % 2 == 0 { 0.5 } else { -0.5 };
+            let right = ((clock / 1013) % 7) as f32 * 0.1 - 0.3;
+            buffer.tick(left, right);
+        }
+        buffer.read_samples(&mut samples);
+
+        samples
+            .chunks(2)
+            .map(|sample| format!("{:.6} {:.6}\n", sample[0], sample[1]))
+            .collect()
+    }
+
+    // Run with UPDATE_GOLDEN=1 to write the reference after an intended change of the output
+    #[test]
+    fn matches_golden_file() {
+        let output = render();
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(GOLDEN_PATH, &output).unwrap();
+        }
+        let golden = std::fs::read_to_string(GOLDEN_PATH).unwrap();
+
+        let lines: Vec<&str> = output.lines().collect();
+        let golden_lines: Vec<&str> = golden.lines().collect();
+        assert_eq!(lines.len(), golden_lines.len());
+        for (index, (line, golden_line)) in lines.iter().zip(&golden_lines).enumerate() {
+            let values = line.split(' ').map(|value| value.parse::<f32>().unwrap());
+            let golden_values = golden_line
+                .split(' ')
+                .map(|value| value.parse::<f32>().unwrap());
+            for (value, golden_value) in values.zip(golden_values) {
+                assert!(
+                    (value - golden_value).abs() <= 2e-6,
+                    "Sample {index} is {line}, but {golden_line} in the golden file"
+                );
+            }
+        }
+    }
+}
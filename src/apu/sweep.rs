@@ -0,0 +1,99 @@
+/*!
+The frequency sweep of the first square channel, controlled by NR10. Every pace ticks of the 128 Hz sweep
+clock of the frame sequencer, the period is shifted right by the step and added to or subtracted from a
+shadow copy of itself. A result above 2047 turns the channel off, even when it is not written back.
+https://gbdev.io/pandocs/Audio_Registers.html#ff10--nr10-channel-1-sweep
+*/
+
+const MAX_PERIOD: u16 = 0x7FF;
+
+pub struct Sweep {
+    // NR10
+    register: u8,
+    enabled: bool,
+    shadow: u16,
+    timer: u8,
+    // A subtraction was calculated since the last trigger. Switching to addition afterwards turns the
+    // channel off
+    subtracted: bool,
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep {
+            register: 0,
+            enabled: false,
+            shadow: 0,
+            timer: 0,
+            subtracted: false,
+        }
+    }
+
+    // Returns whether the write turns the channel off
+    pub fn write(&mut self, value: u8) -> bool {
+        self.register = value & 0x7F;
+        self.subtracted && !self.subtracts()
+    }
+
+    // Returns whether the channel is turned off right away, by an overflowing first calculation
+    pub fn trigger(&mut self, period: u16) -> bool {
+        self.shadow = period;
+        self.timer = self.reload_value();
+        self.enabled = self.pace() != 0 || self.step() != 0;
+        self.subtracted = false;
+
+        self.step() != 0 && self.calculate() > MAX_PERIOD
+    }
+
+    // Returns the new period if it changed, and whether the channel is turned off
+    pub fn clock(&mut self) -> (Option<u16>, bool) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return (None, false);
+        }
+        self.timer = self.reload_value();
+
+        if !self.enabled || self.pace() == 0 {
+            return (None, false);
+        }
+
+        let period = self.calculate();
+        if period > MAX_PERIOD {
+            return (None, true);
+        }
+        if self.step() == 0 {
+            return (None, false);
+        }
+
+        // The new period is checked for overflow once more, without writing it back
+        self.shadow = period;
+        (Some(period), self.calculate() > MAX_PERIOD)
+    }
+
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow >> self.step();
+        if self.subtracts() {
+            self.subtracted = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+
+    // A pace of 0 is treated as 8 by the timer
+    fn reload_value(&self) -> u8 {
+        if self.pace() == 0 { 8 } else { self.pace() }
+    }
+
+    fn pace(&self) -> u8 {
+        (self.register >> 4) & 0x07
+    }
+
+    fn subtracts(&self) -> bool {
+        (self.register & 0x08) == 0x08
+    }
+
+    fn step(&self) -> u8 {
+        self.register & 0x07
+    }
+}
@@ -0,0 +1,90 @@
+/*!
+Writes samples to a WAV file as 16-bit PCM. The sizes in the header are updated after every write, so the
+file is complete even if the emulator is killed while recording.
+http://soundfile.sapp.org/doc/WaveFormat/
+*/
+
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_SAMPLE: u16 = BITS_PER_SAMPLE / 8;
+// The format code of uncompressed PCM
+const FORMAT_PCM: u16 = 1;
+
+// The offsets of the sizes of the RIFF chunk and the data chunk in the header
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 40;
+
+pub struct WavWriter {
+    path: PathBuf,
+    file: File,
+    // The bytes of samples written so far
+    data_size: u32,
+}
+
+impl WavWriter {
+    // Creates the file, replacing an existing one, with the given number of interleaved channels
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        channels: u16,
+        sample_rate: u32,
+    ) -> std::io::Result<WavWriter> {
+        let mut file = File::create(path.as_ref())?;
+
+        let block_align = channels * BYTES_PER_SAMPLE;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_SIZE - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        file.write_all(&header)?;
+
+        Ok(WavWriter {
+            path: path.as_ref().to_path_buf(),
+            file,
+            data_size: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Appends samples between -1.0 and 1.0, interleaved if the file has more than one channel
+    pub fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|&sample| to_pcm(sample).to_le_bytes())
+            .collect();
+        self.file.write_all(&data)?;
+        self.data_size += data.len() as u32;
+
+        self.file.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.file
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+fn to_pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
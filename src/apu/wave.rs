@@ -0,0 +1,142 @@
+/*!
+The wave channel plays the 32 4-bit samples stored in wave RAM at 0xFF30-0xFF3F, upper nibble first. The
+period register holds 2048 minus the length of a sample in units of 2 T-cycles, and NR32 shifts the samples
+right to lower the volume. While the channel plays, the CPU can only access the byte being played.
+https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-3--wave-output
+*/
+
+use crate::apu::length_counter::LengthCounter;
+
+pub const WAVE_RAM_SIZE: usize = 0x10;
+
+pub struct Wave {
+    length: LengthCounter,
+    dac_enabled: bool,
+    enabled: bool,
+
+    // NR32 bits 5-6
+    output_level: u8,
+    period: u16,
+    // The T-cycles until the next sample
+    timer: u16,
+    position: u8,
+    // The sample last read from wave RAM
+    sample: u8,
+    ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        Wave {
+            length: LengthCounter::new(256),
+            dac_enabled: false,
+            enabled: false,
+            output_level: 0,
+            period: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    // Resets the channel as done by turning the APU off, which keeps the contents of wave RAM
+    pub fn reset(&mut self) {
+        *self = Wave {
+            ram: self.ram,
+            ..Wave::new()
+        };
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    pub fn read_ram(&self, offset: usize) -> u8 {
+        self.ram[self.ram_offset(offset)]
+    }
+
+    pub fn write_ram(&mut self, offset: usize, value: u8) {
+        let offset = self.ram_offset(offset);
+        self.ram[offset] = value;
+    }
+
+    fn ram_offset(&self, offset: usize) -> usize {
+        if self.enabled {
+            self.position as usize / 2
+        } else {
+            offset
+        }
+    }
+
+    // Writes NR30-NR34, given by their index. Whether the next step of the frame sequencer clocks the length
+    // counter decides how enabling the length counter and triggering behave
+    pub fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = (value & 0x80) == 0x80;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value as u16),
+            2 => self.output_level = (value >> 5) & 0x03,
+            3 => self.period = (self.period & 0x700) | value as u16,
+            _ => {
+                self.period = (self.period & 0xFF) | ((value as u16 & 0x07) << 8);
+                let trigger = (value & 0x80) == 0x80;
+                let expired = self
+                    .length
+                    .set_enabled((value & 0x40) == 0x40, next_step_clocks_length);
+                if expired && !trigger {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.enabled = self.dac_enabled;
+                    self.length.trigger(next_step_clocks_length);
+                    self.timer = self.reload_value();
+                    self.position = 0;
+                }
+            }
+        }
+    }
+
+    // Advances the channel by one T-cycle
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.reload_value();
+            self.position = (self.position + 1) % 32;
+
+            let byte = self.ram[self.position as usize / 2];
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // The digital output 0-15 fed into the DAC. The output levels mute, play at full, half and quarter volume
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.output_level == 0 {
+            0
+        } else {
+            self.sample >> (self.output_level - 1)
+        }
+    }
+
+    fn reload_value(&self) -> u16 {
+        (2048 - self.period) * 2
+    }
+}
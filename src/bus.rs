@@ -1,21 +1,56 @@
+// Control signals accompanying a value on the bus. The CPU core asserts /RD or /WR on the address bus
+// during an M-cycle to tell the addressed device whether it should drive or latch the data bus
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Signal {
+    // Neither /RD nor /WR is asserted, the value on the bus is not meant for the memory
+    Idle,
+    // /RD is asserted, the addressed device drives the data bus
+    Read,
+    // /WR is asserted, the addressed device latches the value on the data bus
+    Write,
+}
+
+// A snapshot of the address and data bus at the end of an M-cycle
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusCycle {
+    pub signal: Signal,
+    pub address: Option<u16>,
+    pub data: Option<u8>,
+}
+
 pub struct Bus<T: Copy> {
     value: Option<T>,
+    signal: Signal,
 }
 
 impl<T: Copy> Bus<T> {
     pub fn new() -> Self {
-        Bus { value: None }
+        Bus {
+            value: None,
+            signal: Signal::Idle,
+        }
     }
 
+    // Driving a new value onto the bus starts a new transaction, so the control signals are released
     pub fn write(&mut self, data: T) {
-        self.value = Some(data)
+        self.value = Some(data);
+        self.signal = Signal::Idle;
     }
 
     pub fn clear(&mut self) {
-        self.value = None
+        self.value = None;
+        self.signal = Signal::Idle;
     }
 
     pub fn read(&self) -> Option<T> {
         self.value
     }
+
+    pub fn assert_signal(&mut self, signal: Signal) {
+        self.signal = signal
+    }
+
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
 }
@@ -0,0 +1,261 @@
+/*!
+A cartridge holds the game ROM, optional external RAM and the memory bank controller that maps both into
+the address space of the CPU. Cartridges are loaded from .gb and .gbc ROM dumps, whose header is parsed
+and validated before the game is allowed to run.
+https://gbdev.io/pandocs/The_Cartridge_Header.html
+*/
+
+use crate::cartridge::header::{Header, Mapper};
+use crate::cartridge::mbc1::MBC1;
+use crate::cartridge::mbc2::MBC2;
+use crate::cartridge::mbc3::MBC3;
+use crate::cartridge::mbc5::MBC5;
+use crate::cartridge::rom_only::RomOnly;
+use crate::cartridge::{MBC, RumbleCallback};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(std::io::Error),
+    // The ROM is too small to contain a header
+    TooSmall(usize),
+    InvalidLogo,
+    HeaderChecksumMismatch { expected: u8, computed: u8 },
+    InvalidCartridgeType(u8),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    // The ROM dump is smaller than the size declared in the header
+    RomSizeMismatch { declared: usize, actual: usize },
+    UnsupportedMapper(Mapper),
+}
+
+impl Display for CartridgeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CartridgeError::Io(error) => write!(f, "Could not read the ROM: {error}"),
+            CartridgeError::TooSmall(size) => write!(
+                f,
+                "The ROM is {size} bytes large, which is too small to contain a header"
+            ),
+            CartridgeError::InvalidLogo => write!(f, "The Nintendo logo in the header is invalid"),
+            CartridgeError::HeaderChecksumMismatch { expected, computed } => write!(
+                f,
+                "The header checksum is {computed:#04X}, but the header declares {expected:#04X}"
+            ),
+            CartridgeError::InvalidCartridgeType(code) => {
+                write!(f, "The cartridge type {code:#04X} is invalid")
+            }
+            CartridgeError::InvalidRomSize(code) => {
+                write!(f, "The ROM size code {code:#04X} is invalid")
+            }
+            CartridgeError::InvalidRamSize(code) => {
+                write!(f, "The RAM size code {code:#04X} is invalid")
+            }
+            CartridgeError::RomSizeMismatch { declared, actual } => write!(
+                f,
+                "The ROM is {actual} bytes large, but the header declares {declared} bytes"
+            ),
+            CartridgeError::UnsupportedMapper(mapper) => {
+                write!(f, "The memory bank controller {mapper:?} is not supported")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CartridgeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(error: std::io::Error) -> Self {
+        CartridgeError::Io(error)
+    }
+}
+
+pub struct Cartridge {
+    header: Header,
+    mbc: Box<dyn MBC>,
+
+    // Whether the external RAM was written since the last checkpoint
+    ram_modified: bool,
+}
+
+impl Cartridge {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Cartridge, CartridgeError> {
+        Cartridge::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(mut rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let header = Header::parse(&rom)?;
+
+        // Missing banks can not be emulated, but larger dumps still run, as if on a larger ROM chip
+        if rom.len() < header.rom_size {
+            return Err(CartridgeError::RomSizeMismatch {
+                declared: header.rom_size,
+                actual: rom.len(),
+            });
+        }
+        // The mappers wrap the bank numbers with a mask, which needs a size that is a power of two. The
+        // missing part of the chip is not driven and reads as 0xFF
+        rom.resize(rom.len().next_power_of_two(), 0xFF);
+
+        let mbc: Box<dyn MBC> = match header.cartridge_type.mapper {
+            Mapper::None => Box::new(RomOnly::new(rom, header.ram_size)),
+            Mapper::MBC1 => Box::new(MBC1::new(rom, header.ram_size)),
+            // The RAM of the MBC2 is built in, so the header declares no RAM
+            Mapper::MBC2 => Box::new(MBC2::new(rom)),
+            Mapper::MBC3 => Box::new(MBC3::new(rom, header.ram_size, header.cartridge_type.timer)),
+            Mapper::MBC5 => Box::new(MBC5::new(
+                rom,
+                header.ram_size,
+                header.cartridge_type.rumble,
+            )),
+            mapper => return Err(CartridgeError::UnsupportedMapper(mapper)),
+        };
+
+        Ok(Cartridge {
+            header,
+            mbc,
+            ram_modified: false,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    // Only cartridges with a battery keep their RAM and clock while the Game Boy is turned off
+    pub fn has_battery(&self) -> bool {
+        self.header.cartridge_type.battery
+    }
+
+    // Returns whether the external RAM or the clock registers were written since the last call, and resets
+    // the flag
+    pub fn take_ram_modified(&mut self) -> bool {
+        let rtc_modified = self.mbc.rtc_mut().is_some_and(|rtc| rtc.take_modified());
+        std::mem::replace(&mut self.ram_modified, false) | rtc_modified
+    }
+
+    // The contents of a .sav file: the external RAM followed by the clock trailer if there is a clock
+    pub fn save_data(&self) -> Vec<u8> {
+        let mut data = self.mbc.ram().to_vec();
+        if let Some(rtc) = self.mbc.rtc() {
+            data.extend_from_slice(&rtc.encode_trailer(SystemTime::now()));
+        }
+        data
+    }
+
+    // Restores the external RAM and clock from the contents of a .sav file. Returns the time the clock was
+    // saved at, if the file contains one
+    pub fn load_save_data(&mut self, data: &[u8]) -> Option<SystemTime> {
+        let ram = self.mbc.ram_mut();
+        let ram_size = ram.len().min(data.len());
+        ram[..ram_size].copy_from_slice(&data[..ram_size]);
+
+        self.mbc
+            .rtc_mut()
+            .and_then(|rtc| rtc.decode_trailer(&data[ram_size..]))
+    }
+
+    pub fn read_rom(&self, address: u16) -> u8 {
+        self.mbc.read_rom(address)
+    }
+
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        self.mbc.write_rom(address, value)
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.mbc.read_ram(address)
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        self.ram_modified = true;
+        self.mbc.write_ram(address, value)
+    }
+
+    // Lets the frontend observe the rumble motor of the cartridge, if it has one
+    pub fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.mbc.set_rumble_callback(callback)
+    }
+
+    pub fn clock_cycle(&mut self) {
+        self.mbc.clock_cycle()
+    }
+
+    // Advances the real time clock by the time that passed on the host since the given timestamp, so the
+    // clock keeps running while the emulator is closed, like it does on a cartridge with a battery
+    pub fn sync_rtc_to_wall_clock(&mut self, since: SystemTime) {
+        if let Some(rtc) = self.mbc.rtc_mut() {
+            rtc.sync_to_wall_clock(since);
+        }
+    }
+}
+
+#[cfg(test)]
+impl Cartridge {
+    // A 32 KiB ROM without a mapper, with a valid header and each piece of code at its address. The entry
+    // point jumps to 0x0150, right after the header
+    pub fn with_code(code: &[(u16, &[u8])]) -> Cartridge {
+        Cartridge::from_bytes(Cartridge::rom_with_code(code)).unwrap()
+    }
+
+    // The ROM image of with_code
+    pub fn rom_with_code(code: &[(u16, &[u8])]) -> Vec<u8> {
+        use crate::cartridge::header::{LOGO_START, NINTENDO_LOGO};
+
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[LOGO_START..LOGO_START + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        for (address, bytes) in code {
+            let address = *address as usize;
+            rom[address..address + bytes.len()].copy_from_slice(bytes);
+        }
+
+        rom[0x014D] = rom[0x0134..0x014D].iter().fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        });
+        let global_checksum = rom
+            .iter()
+            .fold(0u16, |checksum, byte| checksum.wrapping_add(*byte as u16));
+        rom[0x014E..0x0150].copy_from_slice(&global_checksum.to_be_bytes());
+        rom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smaller_rom_is_rejected() {
+        let mut rom = Cartridge::rom_with_code(&[]);
+        rom.truncate(0x4000);
+
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::RomSizeMismatch {
+                declared: 0x8000,
+                actual: 0x4000
+            })
+        ));
+    }
+
+    #[test]
+    fn larger_rom_is_accepted() {
+        let mut rom = Cartridge::rom_with_code(&[(0x7FFF, &[0x12])]);
+        rom.extend_from_slice(&[0x34; 0x100]);
+
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert!(!cartridge.header().rom_size_matches());
+        assert_eq!(cartridge.header().actual_rom_size, 0x8100);
+        assert_eq!(cartridge.read_rom(0x7FFF), 0x12);
+    }
+}
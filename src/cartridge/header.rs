@@ -0,0 +1,338 @@
+/*!
+The cartridge header occupies the memory area 0x0100-0x014F of every cartridge ROM. It describes the
+game and the hardware on the cartridge, such as the memory bank controller and the size of the ROM and
+RAM. The boot ROM refuses to start a cartridge whose logo or header checksum are incorrect.
+https://gbdev.io/pandocs/The_Cartridge_Header.html
+*/
+
+use crate::cartridge::CartridgeError;
+use std::fmt::{Display, Formatter};
+
+const HEADER_END: usize = 0x0150;
+
+pub const LOGO_START: usize = 0x0104;
+const TITLE_START: usize = 0x0134;
+const MANUFACTURER_CODE_START: usize = 0x013F;
+const CGB_FLAG: usize = 0x0143;
+const NEW_LICENSEE_CODE_START: usize = 0x0144;
+const SGB_FLAG: usize = 0x0146;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const DESTINATION_CODE: usize = 0x014A;
+const OLD_LICENSEE_CODE: usize = 0x014B;
+const VERSION: usize = 0x014C;
+const HEADER_CHECKSUM: usize = 0x014D;
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CgbSupport {
+    // 0x00 and every other value, the game only uses DMG features
+    None,
+    // 0x80, the game supports CGB enhancements but also runs on the DMG
+    Enhanced,
+    // 0xC0, the game only runs on the CGB
+    Only,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mapper {
+    None,
+    MBC1,
+    MBC2,
+    MMM01,
+    MBC3,
+    MBC5,
+    MBC6,
+    MBC7,
+    PocketCamera,
+    TAMA5,
+    HuC3,
+    HuC1,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Result<CartridgeType, CartridgeError> {
+        let (mapper, ram, battery, timer, rumble) = match code {
+            0x00 => (Mapper::None, false, false, false, false),
+            0x01 => (Mapper::MBC1, false, false, false, false),
+            0x02 => (Mapper::MBC1, true, false, false, false),
+            0x03 => (Mapper::MBC1, true, true, false, false),
+            0x05 => (Mapper::MBC2, false, false, false, false),
+            0x06 => (Mapper::MBC2, false, true, false, false),
+            0x08 => (Mapper::None, true, false, false, false),
+            0x09 => (Mapper::None, true, true, false, false),
+            0x0B => (Mapper::MMM01, false, false, false, false),
+            0x0C => (Mapper::MMM01, true, false, false, false),
+            0x0D => (Mapper::MMM01, true, true, false, false),
+            0x0F => (Mapper::MBC3, false, true, true, false),
+            0x10 => (Mapper::MBC3, true, true, true, false),
+            0x11 => (Mapper::MBC3, false, false, false, false),
+            0x12 => (Mapper::MBC3, true, false, false, false),
+            0x13 => (Mapper::MBC3, true, true, false, false),
+            0x19 => (Mapper::MBC5, false, false, false, false),
+            0x1A => (Mapper::MBC5, true, false, false, false),
+            0x1B => (Mapper::MBC5, true, true, false, false),
+            0x1C => (Mapper::MBC5, false, false, false, true),
+            0x1D => (Mapper::MBC5, true, false, false, true),
+            0x1E => (Mapper::MBC5, true, true, false, true),
+            0x20 => (Mapper::MBC6, false, false, false, false),
+            0x22 => (Mapper::MBC7, true, true, false, true),
+            0xFC => (Mapper::PocketCamera, true, false, false, false),
+            0xFD => (Mapper::TAMA5, false, false, false, false),
+            0xFE => (Mapper::HuC3, true, true, true, false),
+            0xFF => (Mapper::HuC1, true, true, false, false),
+            _ => return Err(CartridgeError::InvalidCartridgeType(code)),
+        };
+
+        Ok(CartridgeType {
+            code,
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+pub struct Header {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub licensee_code: String,
+    pub sgb_support: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize,
+    // The size of the ROM dump, which may be larger than the declared size
+    pub actual_rom_size: usize,
+    pub ram_size: usize,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub computed_global_checksum: u16,
+}
+
+impl Display for Header {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "---Header---")?;
+        writeln!(f, "Title:\t\t{}", self.title)?;
+        if let Some(manufacturer_code) = &self.manufacturer_code {
+            writeln!(f, "Manufacturer:\t{manufacturer_code}")?;
+        }
+        writeln!(f, "Licensee:\t{}", self.licensee_code)?;
+        writeln!(f, "CGB:\t\t{:?}", self.cgb_support)?;
+        writeln!(f, "SGB:\t\t{}", self.sgb_support)?;
+        writeln!(f, "Type:\t\t{:?}", self.cartridge_type)?;
+        write!(f, "ROM size:\t{} KiB", self.rom_size / 1024)?;
+        if !self.rom_size_matches() {
+            write!(f, " (actual {} bytes)", self.actual_rom_size)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "RAM size:\t{} KiB", self.ram_size / 1024)?;
+        writeln!(f, "Destination:\t{:?}", self.destination)?;
+        writeln!(f, "Version:\t{}", self.version)?;
+        writeln!(f, "Header checksum:\t{:#04X}", self.header_checksum)?;
+        write!(f, "Global checksum:\t{:#06X}", self.global_checksum)?;
+        if !self.global_checksum_matches() {
+            write!(f, " (computed {:#06X})", self.computed_global_checksum)?;
+        }
+        writeln!(f)?;
+        write!(f, "------------")?;
+        Ok(())
+    }
+}
+
+impl Header {
+    pub fn global_checksum_matches(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    pub fn rom_size_matches(&self) -> bool {
+        self.rom_size == self.actual_rom_size
+    }
+
+    pub fn parse(rom: &[u8]) -> Result<Header, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+
+        if rom[LOGO_START..LOGO_START + NINTENDO_LOGO.len()] != NINTENDO_LOGO {
+            return Err(CartridgeError::InvalidLogo);
+        }
+
+        let header_checksum = rom[HEADER_CHECKSUM];
+        let computed_header_checksum = Header::compute_header_checksum(rom);
+        if header_checksum != computed_header_checksum {
+            return Err(CartridgeError::HeaderChecksumMismatch {
+                expected: header_checksum,
+                computed: computed_header_checksum,
+            });
+        }
+
+        let cgb_support = match rom[CGB_FLAG] {
+            0x80 => CgbSupport::Enhanced,
+            0xC0 => CgbSupport::Only,
+            _ => CgbSupport::None,
+        };
+
+        // Newer cartridges shortened the title to fit a manufacturer code and the CGB flag
+        let (title_end, manufacturer_code) = if cgb_support == CgbSupport::None {
+            (CGB_FLAG + 1, None)
+        } else {
+            let code = &rom[MANUFACTURER_CODE_START..CGB_FLAG];
+            if code
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+            {
+                (MANUFACTURER_CODE_START, Some(Header::ascii(code)))
+            } else {
+                (CGB_FLAG, None)
+            }
+        };
+
+        // An old licensee code of 0x33 means the new licensee code is used instead
+        let licensee_code = if rom[OLD_LICENSEE_CODE] == 0x33 {
+            Header::ascii(&rom[NEW_LICENSEE_CODE_START..SGB_FLAG])
+        } else {
+            format!("{:02X}", rom[OLD_LICENSEE_CODE])
+        };
+
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => (32 * 1024) << code,
+            code => return Err(CartridgeError::InvalidRomSize(code)),
+        };
+
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            // 0x01 is unofficial and was never used by a released game
+            0x01 => 2 * 1024,
+            0x02 => 8 * 1024,
+            0x03 => 32 * 1024,
+            0x04 => 128 * 1024,
+            0x05 => 64 * 1024,
+            code => return Err(CartridgeError::InvalidRamSize(code)),
+        };
+
+        // The boot ROM never verifies the global checksum, so games with a wrong one still run
+        let global_checksum =
+            ((rom[GLOBAL_CHECKSUM] as u16) << 8) | rom[GLOBAL_CHECKSUM + 1] as u16;
+
+        Ok(Header {
+            title: Header::ascii(&rom[TITLE_START..title_end]),
+            manufacturer_code,
+            cgb_support,
+            licensee_code,
+            sgb_support: rom[SGB_FLAG] == 0x03,
+            cartridge_type: CartridgeType::from_code(rom[CARTRIDGE_TYPE])?,
+            rom_size,
+            actual_rom_size: rom.len(),
+            ram_size,
+            destination: if rom[DESTINATION_CODE] == 0x00 {
+                Destination::Japan
+            } else {
+                Destination::Overseas
+            },
+            version: rom[VERSION],
+            header_checksum,
+            global_checksum,
+            computed_global_checksum: Header::compute_global_checksum(rom),
+        })
+    }
+
+    // The checksum over the header bytes 0x0134-0x014C, which is verified by the boot ROM
+    fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[TITLE_START..HEADER_CHECKSUM]
+            .iter()
+            .fold(0u8, |checksum, byte| {
+                checksum.wrapping_sub(*byte).wrapping_sub(1)
+            })
+    }
+
+    // The sum of all bytes in the ROM, except for the two global checksum bytes themselves
+    fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(i, _)| *i != GLOBAL_CHECKSUM && *i != GLOBAL_CHECKSUM + 1)
+            .fold(0u16, |checksum, (_, byte)| {
+                checksum.wrapping_add(*byte as u16)
+            })
+    }
+
+    fn ascii(bytes: &[u8]) -> String {
+        bytes
+            .iter()
+            .take_while(|c| **c != 0x00)
+            .map(|c| {
+                if c.is_ascii_graphic() || *c == b' ' {
+                    *c as char
+                } else {
+                    '?'
+                }
+            })
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0x00; 0x8000];
+        rom[LOGO_START..LOGO_START + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        rom[HEADER_CHECKSUM] = Header::compute_header_checksum(&rom);
+        rom
+    }
+
+    #[test]
+    fn global_checksum_mismatch_is_accepted() {
+        let mut rom = rom();
+        rom[GLOBAL_CHECKSUM] = 0x12;
+        rom[GLOBAL_CHECKSUM + 1] = 0x34;
+
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.global_checksum, 0x1234);
+        assert_eq!(
+            header.computed_global_checksum,
+            Header::compute_global_checksum(&rom)
+        );
+        assert!(!header.global_checksum_matches());
+    }
+
+    #[test]
+    fn header_checksum_mismatch_is_rejected() {
+        let mut rom = rom();
+        rom[HEADER_CHECKSUM] ^= 0xFF;
+
+        assert!(matches!(
+            Header::parse(&rom),
+            Err(CartridgeError::HeaderChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+/*!
+A Memory Bank Controller (MBC) sits on the cartridge between the address bus and the ROM and RAM chips.
+It maps the 0x0000-0x7FFF and 0xA000-0xBFFF address ranges to banks of the cartridge memory. Writes to
+the ROM address range do not reach the ROM, but control the registers of the MBC instead.
+https://gbdev.io/pandocs/MBCs.html
+*/
+
+use crate::cartridge::rtc::RTC;
+
+// Called with the new state of the rumble motor whenever the game turns it on or off
+pub type RumbleCallback = Box<dyn FnMut(bool)>;
+
+pub trait MBC {
+    // Reads from the ROM address range 0x0000-0x7FFF
+    fn read_rom(&self, address: u16) -> u8;
+
+    // Writes to the ROM address range 0x0000-0x7FFF, which control the registers of the MBC
+    fn write_rom(&mut self, address: u16, value: u8);
+
+    // Reads from the external RAM address range 0xA000-0xBFFF
+    fn read_ram(&self, address: u16) -> u8;
+
+    // Writes to the external RAM address range 0xA000-0xBFFF
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    // The contents of the external RAM, as stored in a save file
+    fn ram(&self) -> &[u8];
+
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    // Advances the hardware on the cartridge that runs independently of the CPU, such as a clock, by
+    // one M-cycle at normal speed
+    fn clock_cycle(&mut self) {}
+
+    fn rtc(&self) -> Option<&RTC> {
+        None
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut RTC> {
+        None
+    }
+
+    // Cartridges without a rumble motor never call the callback
+    fn set_rumble_callback(&mut self, _callback: RumbleCallback) {}
+}
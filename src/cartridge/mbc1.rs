@@ -0,0 +1,219 @@
+/*!
+The MBC1 supports up to 2 MiB of ROM and up to 32 KiB of banked RAM. Its two bank registers are combined
+differently depending on the banking mode: in the simple mode only the switchable ROM bank uses the
+upper register, while in the advanced mode it also selects the RAM bank and the bank mapped at
+0x0000-0x3FFF. MBC1M multicarts wire the upper register one bit lower, to switch between 256 KiB games.
+https://gbdev.io/pandocs/MBC1.html
+*/
+
+use crate::cartridge::MBC;
+use crate::cartridge::header::{LOGO_START, NINTENDO_LOGO};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+const MULTICART_SIZE: usize = 1024 * 1024;
+const MULTICART_GAME_BANKS: usize = 0x10;
+
+pub struct MBC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+
+    // 0x0000-0x1FFF, 0xA in the lower nibble enables the RAM
+    ram_enabled: bool,
+    // 0x2000-0x3FFF, the lower 5 bits of the ROM bank number
+    bank_1: u8,
+    // 0x4000-0x5FFF, the RAM bank number or the upper 2 bits of the ROM bank number
+    bank_2: u8,
+    // 0x6000-0x7FFF
+    advanced_banking: bool,
+
+    multicart: bool,
+}
+
+impl MBC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> MBC1 {
+        let multicart = MBC1::is_multicart(&rom);
+
+        MBC1 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            bank_1: 1,
+            bank_2: 0,
+            advanced_banking: false,
+            multicart,
+        }
+    }
+
+    // Multicarts are 1 MiB large and contain a game with its own header in every 256 KiB block. Since
+    // a single game could never switch to those blocks on an MBC1M, checking the second one is enough
+    fn is_multicart(rom: &[u8]) -> bool {
+        let logo_start = MULTICART_GAME_BANKS * ROM_BANK_SIZE + LOGO_START;
+        rom.len() == MULTICART_SIZE
+            && rom[logo_start..logo_start + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+    }
+
+    // The bit position the upper bank register is wired to
+    fn bank_2_shift(&self) -> u8 {
+        if self.multicart { 4 } else { 5 }
+    }
+
+    fn rom_offset(&self, bank: u8, address: u16) -> usize {
+        // Bank numbers beyond the size of the ROM wrap around, as the upper address lines are not connected
+        ((bank as usize * ROM_BANK_SIZE) | (address as usize & (ROM_BANK_SIZE - 1)))
+            & (self.rom.len() - 1)
+    }
+
+    fn ram_offset(&self, address: u16) -> usize {
+        let bank = if self.advanced_banking {
+            self.bank_2
+        } else {
+            0
+        };
+        ((bank as usize * RAM_BANK_SIZE) | (address as usize & (RAM_BANK_SIZE - 1)))
+            & (self.ram.len() - 1)
+    }
+}
+
+impl MBC for MBC1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            if self.advanced_banking {
+                self.bank_2 << self.bank_2_shift()
+            } else {
+                0
+            }
+        } else {
+            let bank_1_mask = (1 << self.bank_2_shift()) - 1;
+            (self.bank_2 << self.bank_2_shift()) | (self.bank_1 & bank_1_mask)
+        };
+
+        self.rom[self.rom_offset(bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = (value & 0x0F) == 0x0A,
+            0x2000..=0x3FFF => {
+                // Bank 0 can not be selected here, as the MBC treats it as bank 1. Only the 5 bits of the
+                // register are checked, so the banks 0x20, 0x40 and 0x60 are unreachable as well
+                self.bank_1 = value & 0x1F;
+                if self.bank_1 == 0 {
+                    self.bank_1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank_2 = value & 0x03,
+            _ => self.advanced_banking = (value & 0x01) == 0x01,
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+
+        self.ram[self.ram_offset(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+
+        let offset = self.ram_offset(address);
+        self.ram[offset] = value;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A ROM whose banks start with their own bank number
+    fn numbered_rom(size: usize) -> Vec<u8> {
+        let mut rom = vec![0; size];
+        for bank in 0..size / ROM_BANK_SIZE {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn bank_0_selects_bank_1() {
+        let mut mbc = MBC1::new(numbered_rom(2 * 1024 * 1024), 0);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x01);
+
+        // Only the lower 5 bits are checked, so 0x20 becomes 0x21
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 0x25);
+    }
+
+    #[test]
+    fn advanced_banking_maps_upper_bits_at_0x0000() {
+        let mut mbc = MBC1::new(numbered_rom(2 * 1024 * 1024), 0);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mbc.read_rom(0x0000), 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x43);
+
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x40);
+        assert_eq!(mbc.read_rom(0x4000), 0x43);
+    }
+
+    #[test]
+    fn advanced_banking_selects_ram_bank() {
+        let mut mbc = MBC1::new(numbered_rom(0x8000), 4 * RAM_BANK_SIZE);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(0xA000, 0x11);
+        assert_eq!(mbc.ram()[0], 0x11);
+
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_ram(0xA000, 0x22);
+        assert_eq!(mbc.ram()[2 * RAM_BANK_SIZE], 0x22);
+        assert_eq!(mbc.read_ram(0xA000), 0x22);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn multicart_wires_upper_bits_one_lower() {
+        let mut rom = numbered_rom(MULTICART_SIZE);
+        let logo_start = MULTICART_GAME_BANKS * ROM_BANK_SIZE + LOGO_START;
+        rom[logo_start..logo_start + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        let mut mbc = MBC1::new(rom, 0);
+
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x12);
+        assert_eq!(mbc.read_rom(0x4000), 0x12);
+
+        // Bit 4 is not wired, but still counts for the check of bank 0
+        mbc.write_rom(0x2000, 0x10);
+        assert_eq!(mbc.read_rom(0x4000), 0x10);
+
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_rom(0x4000, 0x03);
+        assert_eq!(mbc.read_rom(0x0000), 0x30);
+    }
+
+    #[test]
+    fn rom_without_second_logo_is_no_multicart() {
+        let mut mbc = MBC1::new(numbered_rom(MULTICART_SIZE), 0);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x12);
+        assert_eq!(mbc.read_rom(0x4000), 0x32);
+    }
+}
@@ -0,0 +1,132 @@
+/*!
+The MBC2 supports up to 256 KiB of ROM and contains 512 half-bytes of RAM itself. Only the lower 4 bits
+of the RAM are connected to the data bus, and since only 9 address lines reach it, the RAM repeats
+throughout the external RAM area. Both registers share the 0x0000-0x3FFF range and are selected by
+bit 8 of the address.
+https://gbdev.io/pandocs/MBC2.html
+*/
+
+use crate::cartridge::MBC;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_SIZE: usize = 0x200;
+
+pub struct MBC2 {
+    rom: Vec<u8>,
+    ram: [u8; RAM_SIZE],
+
+    // Address bit 8 clear, 0xA in the lower nibble enables the RAM
+    ram_enabled: bool,
+    // Address bit 8 set, the lower 4 bits of the ROM bank number
+    rom_bank: u8,
+}
+
+impl MBC2 {
+    pub fn new(rom: Vec<u8>) -> MBC2 {
+        MBC2 {
+            rom,
+            ram: [0; RAM_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl MBC for MBC2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank };
+        let offset = ((bank as usize * ROM_BANK_SIZE) | (address as usize & (ROM_BANK_SIZE - 1)))
+            & (self.rom.len() - 1);
+
+        self.rom[offset]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        // The registers do not respond to 0x4000-0x7FFF
+        if address >= 0x4000 {
+            return;
+        }
+
+        if (address & 0x0100) == 0 {
+            self.ram_enabled = (value & 0x0F) == 0x0A;
+        } else {
+            self.rom_bank = value & 0x0F;
+            if self.rom_bank == 0 {
+                self.rom_bank = 1;
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        // The upper 4 bits are not driven and read as 1
+        0xF0 | self.ram[address as usize & (RAM_SIZE - 1)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+
+        self.ram[address as usize & (RAM_SIZE - 1)] = value & 0x0F;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A ROM whose banks start with their own bank number
+    fn numbered_rom() -> Vec<u8> {
+        let mut rom = vec![0; 256 * 1024];
+        for bank in 0..rom.len() / ROM_BANK_SIZE {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn address_bit_8_selects_the_register() {
+        let mut mbc = MBC2::new(numbered_rom());
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x2100, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 0x05);
+        mbc.write_rom(0x0100, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x01);
+
+        mbc.write_rom(0x3E00, 0x0A);
+        assert_eq!(mbc.read_ram(0xA000), 0xF0);
+
+        // The registers do not respond above 0x3FFF, even with bit 8 set
+        mbc.write_rom(0x4100, 0x03);
+        assert_eq!(mbc.read_rom(0x4000), 0x01);
+    }
+
+    #[test]
+    fn ram_has_4_bits_and_repeats_every_512_bytes() {
+        let mut mbc = MBC2::new(numbered_rom());
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA001, 0xA5);
+        assert_eq!(mbc.ram()[1], 0x05);
+        assert_eq!(mbc.read_ram(0xA001), 0xF5);
+        assert_eq!(mbc.read_ram(0xA201), 0xF5);
+        assert_eq!(mbc.read_ram(0xBE01), 0xF5);
+
+        mbc.write_ram(0xB3FF, 0x0C);
+        assert_eq!(mbc.read_ram(0xA1FF), 0xFC);
+    }
+}
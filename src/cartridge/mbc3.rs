@@ -0,0 +1,158 @@
+/*!
+The MBC3 supports up to 2 MiB of ROM (4 MiB on the MBC30), up to 32 KiB of banked RAM (64 KiB on the MBC30)
+and optionally a real time clock. The registers of the clock are mapped into the external RAM area in
+place of a RAM bank.
+https://gbdev.io/pandocs/MBC3.html
+*/
+
+use crate::cartridge::MBC;
+use crate::cartridge::rtc::RTC;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+// Larger ROMs can only be addressed by the MBC30, which has an 8-bit ROM bank register
+const MBC3_MAX_ROM_SIZE: usize = 2 * 1024 * 1024;
+
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: Option<RTC>,
+
+    // 0x0000-0x1FFF, 0xA in the lower nibble enables the RAM and the RTC registers
+    ram_enabled: bool,
+    // 0x2000-0x3FFF
+    rom_bank: u8,
+    // 0x4000-0x5FFF, 0x00-0x07 select a RAM bank, 0x08-0x0C select an RTC register
+    ram_bank: u8,
+}
+
+impl MBC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rtc: bool) -> MBC3 {
+        MBC3 {
+            rom,
+            ram: vec![0; ram_size],
+            rtc: if has_rtc { Some(RTC::new()) } else { None },
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> usize {
+        ((self.ram_bank as usize * RAM_BANK_SIZE) | (address as usize & (RAM_BANK_SIZE - 1)))
+            & (self.ram.len() - 1)
+    }
+}
+
+impl MBC for MBC3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank };
+        let offset = ((bank as usize * ROM_BANK_SIZE) | (address as usize & (ROM_BANK_SIZE - 1)))
+            & (self.rom.len() - 1);
+
+        self.rom[offset]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = (value & 0x0F) == 0x0A,
+            0x2000..=0x3FFF => {
+                let mask = if self.rom.len() > MBC3_MAX_ROM_SIZE {
+                    0xFF
+                } else {
+                    0x7F
+                };
+                // Unlike the MBC1, the whole register is checked, so only bank 0 itself is unreachable
+                self.rom_bank = value & mask;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match self.ram_bank {
+            0x00..=0x07 if !self.ram.is_empty() => self.ram[self.ram_offset(address)],
+            0x08..=0x0C => self
+                .rtc
+                .as_ref()
+                .map_or(0xFF, |rtc| rtc.read(self.ram_bank)),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+
+        match self.ram_bank {
+            0x00..=0x07 if !self.ram.is_empty() => {
+                let offset = self.ram_offset(address);
+                self.ram[offset] = value;
+            }
+            0x08..=0x0C => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write(self.ram_bank, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn clock_cycle(&mut self) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.clock_cycle();
+        }
+    }
+
+    fn rtc(&self) -> Option<&RTC> {
+        self.rtc.as_ref()
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut RTC> {
+        self.rtc.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtc_registers_are_latched_through_the_ram_area() {
+        let mut mbc = MBC3::new(vec![0; 0x8000], 0x2000, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(0xA000, 30);
+        mbc.rtc_mut().unwrap().advance_seconds(2);
+        assert_eq!(mbc.read_ram(0xA000), 30);
+
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 32);
+
+        // Bank 0x00 selects the RAM again
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+    }
+}
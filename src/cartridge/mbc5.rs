@@ -0,0 +1,196 @@
+/*!
+The MBC5 supports up to 8 MiB of ROM with a 9-bit ROM bank number and up to 128 KiB of RAM in 16 banks.
+On cartridges with a rumble motor, bit 3 of the RAM bank register drives the motor instead, which limits
+those cartridges to 8 RAM banks.
+https://gbdev.io/pandocs/MBC5.html
+*/
+
+use crate::cartridge::MBC;
+use crate::cartridge::mbc::RumbleCallback;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+const RUMBLE_BIT: u8 = 0x08;
+
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+
+    // 0x0000-0x1FFF, only exactly 0x0A enables the RAM
+    ram_enabled: bool,
+    // 0x2000-0x2FFF for the lower 8 bits and 0x3000-0x3FFF for bit 8
+    rom_bank: u16,
+    // 0x4000-0x5FFF
+    ram_bank: u8,
+
+    has_rumble: bool,
+    motor_on: bool,
+    rumble_callback: Option<RumbleCallback>,
+}
+
+impl MBC5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> MBC5 {
+        MBC5 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            motor_on: false,
+            rumble_callback: None,
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> usize {
+        ((self.ram_bank as usize * RAM_BANK_SIZE) | (address as usize & (RAM_BANK_SIZE - 1)))
+            & (self.ram.len() - 1)
+    }
+
+    fn set_motor(&mut self, motor_on: bool) {
+        if self.motor_on == motor_on {
+            return;
+        }
+
+        self.motor_on = motor_on;
+        if let Some(callback) = self.rumble_callback.as_mut() {
+            callback(motor_on);
+        }
+    }
+}
+
+impl MBC for MBC5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        // Unlike on the other MBCs, bank 0 can also be mapped to 0x4000-0x7FFF
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank };
+        let offset = ((bank as usize * ROM_BANK_SIZE) | (address as usize & (ROM_BANK_SIZE - 1)))
+            & (self.rom.len() - 1);
+
+        self.rom[offset]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (((value & 0x01) as u16) << 8)
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.ram_bank = value & 0x07;
+                    self.set_motor((value & RUMBLE_BIT) == RUMBLE_BIT);
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+
+        self.ram[self.ram_offset(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+
+        let offset = self.ram_offset(address);
+        self.ram[offset] = value;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.rumble_callback = Some(callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // A ROM whose banks start with their own 16-bit bank number
+    fn numbered_rom(size: usize) -> Vec<u8> {
+        let mut rom = vec![0; size];
+        for bank in 0..size / ROM_BANK_SIZE {
+            let offset = bank * ROM_BANK_SIZE;
+            rom[offset..offset + 2].copy_from_slice(&(bank as u16).to_le_bytes());
+        }
+        rom
+    }
+
+    fn read_bank(mbc: &MBC5) -> u16 {
+        u16::from_le_bytes([mbc.read_rom(0x4000), mbc.read_rom(0x4001)])
+    }
+
+    #[test]
+    fn rom_bank_has_9_bits() {
+        let mut mbc = MBC5::new(numbered_rom(8 * 1024 * 1024), 0, false);
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(read_bank(&mbc), 0x105);
+
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(read_bank(&mbc), 0x1FF);
+
+        mbc.write_rom(0x3000, 0x00);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(read_bank(&mbc), 0x000);
+    }
+
+    #[test]
+    fn ram_is_only_enabled_by_0x0a() {
+        let mut mbc = MBC5::new(numbered_rom(0x8000), RAM_BANK_SIZE, false);
+        mbc.write_rom(0x0000, 0x1A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+    }
+
+    #[test]
+    fn ram_bank_has_4_bits_without_rumble() {
+        let mut mbc = MBC5::new(numbered_rom(0x8000), 16 * RAM_BANK_SIZE, false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0B);
+        mbc.write_ram(0xA000, 0x34);
+        assert_eq!(mbc.ram()[0x0B * RAM_BANK_SIZE], 0x34);
+    }
+
+    #[test]
+    fn rumble_bit_drives_the_motor_instead_of_the_ram_bank() {
+        let mut mbc = MBC5::new(numbered_rom(0x8000), 16 * RAM_BANK_SIZE, true);
+        let motor = Rc::new(RefCell::new(Vec::new()));
+        let calls = motor.clone();
+        mbc.set_rumble_callback(Box::new(move |motor_on| calls.borrow_mut().push(motor_on)));
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0B);
+        mbc.write_ram(0xA000, 0x34);
+        assert_eq!(mbc.ram()[0x03 * RAM_BANK_SIZE], 0x34);
+        assert_eq!(mbc.ram()[0x0B * RAM_BANK_SIZE], 0x00);
+
+        // The callback is only called when the state of the motor changes
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x4000, 0x03);
+        assert_eq!(*motor.borrow(), [true, false]);
+    }
+}
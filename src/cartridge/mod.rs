@@ -0,0 +1,15 @@
+mod cartridge;
+mod header;
+mod mbc;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod rom_only;
+mod rtc;
+mod save;
+
+pub use cartridge::{Cartridge, CartridgeError};
+pub use header::{CgbSupport, Header};
+pub use mbc::{MBC, RumbleCallback};
+pub use save::SaveFile;
@@ -0,0 +1,50 @@
+/*!
+Cartridges without a memory bank controller contain 32 KiB of ROM mapped directly to 0x0000-0x7FFF,
+and optionally up to 8 KiB of RAM mapped directly to 0xA000-0xBFFF.
+https://gbdev.io/pandocs/nombc.html
+*/
+
+use crate::cartridge::MBC;
+
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> RomOnly {
+        RomOnly {
+            rom,
+            ram: vec![0; ram_size],
+        }
+    }
+}
+
+impl MBC for RomOnly {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom.get(address as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.ram
+            .get((address - 0xA000) as usize)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(byte) = self.ram.get_mut((address - 0xA000) as usize) {
+            *byte = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
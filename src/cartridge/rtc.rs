@@ -0,0 +1,385 @@
+/*!
+The Real Time Clock (RTC) of the MBC3 counts seconds, minutes, hours and days from a 32768 Hz crystal on
+the cartridge, even while the Game Boy is turned off. The game reads a snapshot of the counters, which is
+taken by latching them. Here, the clock advances with the emulated cycles so runs are deterministic, and
+only follows the host clock when explicitly synced to it.
+https://gbdev.io/pandocs/MBC3.html
+*/
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The RTC is clocked independently of the CPU speed, so a second always lasts as long as 2^20 M-cycles
+// at normal speed
+const CYCLES_PER_SECOND: u32 = 1 << 20;
+
+const SECONDS: usize = 0;
+const MINUTES: usize = 1;
+const HOURS: usize = 2;
+const DAYS_LOW: usize = 3;
+const DAYS_HIGH: usize = 4;
+
+// Bits of the DH register
+const DAY_HIGH_BIT: u8 = 0x01;
+const HALT_BIT: u8 = 0x40;
+const DAY_CARRY_BIT: u8 = 0x80;
+
+// Most emulators append the clock to the save file as the live and latched registers, each padded to
+// 32 bits, followed by a 64-bit UNIX timestamp of when the file was written. Older versions of the
+// format only used a 32-bit timestamp
+pub const TRAILER_SIZE: usize = 48;
+const SHORT_TRAILER_SIZE: usize = 44;
+
+pub struct RTC {
+    // S, M, H, DL, DH
+    registers: [u8; 5],
+    latched: [u8; 5],
+    cycles: u32,
+    last_latch_write: u8,
+    // Whether the game wrote or latched the registers since the last call to take_modified
+    modified: bool,
+}
+
+impl RTC {
+    pub fn new() -> RTC {
+        RTC {
+            registers: [0; 5],
+            latched: [0; 5],
+            cycles: 0,
+            last_latch_write: 0xFF,
+            modified: false,
+        }
+    }
+
+    // The register index of the RTC registers 0x08-0x0C
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let index = (register - 0x08) as usize;
+        let value = match index {
+            SECONDS | MINUTES => value & 0x3F,
+            HOURS => value & 0x1F,
+            DAYS_LOW => value,
+            _ => value & (DAY_HIGH_BIT | HALT_BIT | DAY_CARRY_BIT),
+        };
+
+        // Writing the seconds restarts the current second
+        if index == SECONDS {
+            self.cycles = 0;
+        }
+        self.registers[index] = value;
+        self.latched[index] = value;
+        self.modified = true;
+    }
+
+    // Writing 0x00 and then 0x01 to 0x6000-0x7FFF copies the counters into the latched registers
+    pub fn write_latch(&mut self, value: u8) {
+        if self.last_latch_write == 0x00 && value == 0x01 {
+            self.latched = self.registers;
+            self.modified = true;
+        }
+        self.last_latch_write = value;
+    }
+
+    // The counters keep running on their own, so only the registers set by the game make the save file
+    // outdated
+    pub fn take_modified(&mut self) -> bool {
+        std::mem::replace(&mut self.modified, false)
+    }
+
+    pub fn clock_cycle(&mut self) {
+        if self.halted() {
+            return;
+        }
+
+        self.cycles += 1;
+        if self.cycles == CYCLES_PER_SECOND {
+            self.cycles = 0;
+            self.advance_second();
+        }
+    }
+
+    // Advances the clock by the given amount of seconds, as if it had been running for that long
+    pub fn advance_seconds(&mut self, seconds: u64) {
+        if self.halted() {
+            return;
+        }
+
+        // Out of range values do not carry over until they wrapped around, so step through those first
+        let mut seconds = seconds;
+        while seconds > 0 && !self.in_range() {
+            self.advance_second();
+            seconds -= 1;
+        }
+
+        let total = self.registers[SECONDS] as u64
+            + 60 * self.registers[MINUTES] as u64
+            + 60 * 60 * self.registers[HOURS] as u64
+            + 24 * 60 * 60 * self.days() as u64
+            + seconds;
+
+        let days = total / (24 * 60 * 60);
+        if days >= 512 {
+            self.registers[DAYS_HIGH] |= DAY_CARRY_BIT;
+        }
+        self.registers[SECONDS] = (total % 60) as u8;
+        self.registers[MINUTES] = (total / 60 % 60) as u8;
+        self.registers[HOURS] = (total / (60 * 60) % 24) as u8;
+        self.set_days((days % 512) as u16);
+    }
+
+    // Advances the clock by the time that passed on the host since the given timestamp, e.g. the time a
+    // save file was written
+    pub fn sync_to_wall_clock(&mut self, since: SystemTime) {
+        let elapsed = SystemTime::now()
+            .duration_since(since)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.advance_seconds(elapsed);
+    }
+
+    // Restores the counters from a save file. The progress towards the next second is not saved
+    pub fn restore(&mut self, registers: [u8; 5], latched: [u8; 5]) {
+        self.registers = registers;
+        self.latched = latched;
+        self.cycles = 0;
+    }
+
+    // Encodes the clock in the trailer format, all values being little endian
+    pub fn encode_trailer(&self, timestamp: SystemTime) -> [u8; TRAILER_SIZE] {
+        let mut trailer = [0; TRAILER_SIZE];
+        for (i, value) in self.registers.iter().chain(self.latched.iter()).enumerate() {
+            trailer[i * 4] = *value;
+        }
+
+        let seconds = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        trailer[40..].copy_from_slice(&seconds.to_le_bytes());
+        trailer
+    }
+
+    // Restores the clock from a trailer and returns the time it was saved at, or None if the trailer has
+    // neither of the known sizes
+    pub fn decode_trailer(&mut self, trailer: &[u8]) -> Option<SystemTime> {
+        let seconds = match trailer.len() {
+            TRAILER_SIZE => u64::from_le_bytes(trailer[40..48].try_into().unwrap()),
+            SHORT_TRAILER_SIZE => u32::from_le_bytes(trailer[40..44].try_into().unwrap()) as u64,
+            _ => return None,
+        };
+
+        let mut registers = [0; 5];
+        let mut latched = [0; 5];
+        for i in 0..5 {
+            registers[i] = trailer[i * 4];
+            latched[i] = trailer[(i + 5) * 4];
+        }
+        self.restore(registers, latched);
+
+        Some(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    fn halted(&self) -> bool {
+        (self.registers[DAYS_HIGH] & HALT_BIT) == HALT_BIT
+    }
+
+    fn days(&self) -> u16 {
+        (((self.registers[DAYS_HIGH] & DAY_HIGH_BIT) as u16) << 8) | self.registers[DAYS_LOW] as u16
+    }
+
+    fn set_days(&mut self, days: u16) {
+        self.registers[DAYS_LOW] = days as u8;
+        self.registers[DAYS_HIGH] =
+            (self.registers[DAYS_HIGH] & !DAY_HIGH_BIT) | ((days >> 8) as u8 & DAY_HIGH_BIT);
+    }
+
+    fn in_range(&self) -> bool {
+        self.registers[SECONDS] < 60 && self.registers[MINUTES] < 60 && self.registers[HOURS] < 24
+    }
+
+    // Each counter only carries into the next one when it reaches its regular limit. Values written out of
+    // range count up to the width of their register and wrap to 0 without a carry
+    fn advance_second(&mut self) {
+        self.registers[SECONDS] = (self.registers[SECONDS] + 1) & 0x3F;
+        if self.registers[SECONDS] != 60 {
+            return;
+        }
+        self.registers[SECONDS] = 0;
+
+        self.registers[MINUTES] = (self.registers[MINUTES] + 1) & 0x3F;
+        if self.registers[MINUTES] != 60 {
+            return;
+        }
+        self.registers[MINUTES] = 0;
+
+        self.registers[HOURS] = (self.registers[HOURS] + 1) & 0x1F;
+        if self.registers[HOURS] != 24 {
+            return;
+        }
+        self.registers[HOURS] = 0;
+
+        let days = self.days() + 1;
+        if days == 512 {
+            self.registers[DAYS_HIGH] |= DAY_CARRY_BIT;
+        }
+        self.set_days(days % 512);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_write_marks_modified() {
+        let mut rtc = RTC::new();
+        assert!(!rtc.take_modified());
+
+        rtc.write(0x0A, 0x12);
+        assert!(rtc.take_modified());
+        assert!(!rtc.take_modified());
+    }
+
+    // Sets the counters through the registers 0x08-0x0C, as a game would
+    fn rtc_with(registers: [u8; 5]) -> RTC {
+        let mut rtc = RTC::new();
+        for (register, value) in (0x08..=0x0C).zip(registers) {
+            rtc.write(register, value);
+        }
+        rtc
+    }
+
+    fn latch(rtc: &mut RTC) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        [0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|register| rtc.read(register))
+    }
+
+    #[test]
+    fn second_rolls_over_into_minutes_hours_and_days() {
+        let mut rtc = rtc_with([59, 59, 23, 0xFF, 0x00]);
+        for _ in 0..CYCLES_PER_SECOND - 1 {
+            rtc.clock_cycle();
+        }
+        assert_eq!(latch(&mut rtc), [59, 59, 23, 0xFF, 0x00]);
+
+        rtc.clock_cycle();
+        assert_eq!(latch(&mut rtc), [0, 0, 0, 0x00, DAY_HIGH_BIT]);
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_carry() {
+        let mut rtc = rtc_with([59, 59, 23, 0xFF, DAY_HIGH_BIT]);
+        rtc.advance_seconds(1);
+        assert_eq!(latch(&mut rtc), [0, 0, 0, 0x00, DAY_CARRY_BIT]);
+
+        // The carry stays set until the game clears it
+        rtc.advance_seconds(24 * 60 * 60);
+        assert_eq!(latch(&mut rtc), [0, 0, 0, 0x01, DAY_CARRY_BIT]);
+
+        let mut rtc = rtc_with([0, 0, 0, 0x00, 0x00]);
+        rtc.advance_seconds(600 * 24 * 60 * 60 + 5);
+        assert_eq!(latch(&mut rtc), [5, 0, 0, 88, DAY_CARRY_BIT]);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut rtc = rtc_with([10, 20, 3, 0x04, HALT_BIT]);
+        for _ in 0..CYCLES_PER_SECOND {
+            rtc.clock_cycle();
+        }
+        rtc.advance_seconds(1000);
+        assert_eq!(latch(&mut rtc), [10, 20, 3, 0x04, HALT_BIT]);
+
+        rtc.write(0x0C, 0x00);
+        rtc.advance_seconds(1);
+        assert_eq!(latch(&mut rtc), [11, 20, 3, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn out_of_range_values_wrap_without_carry() {
+        let mut rtc = rtc_with([63, 0, 0, 0x00, 0x00]);
+        rtc.advance_seconds(1);
+        assert_eq!(latch(&mut rtc), [0, 0, 0, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn latch_needs_0x00_then_0x01() {
+        let mut rtc = rtc_with([0, 0, 0, 0x00, 0x00]);
+        rtc.advance_seconds(5);
+        assert_eq!(rtc.read(0x08), 0);
+
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 0);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x02);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 0);
+
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 5);
+
+        // The latched registers keep their values while the clock runs on
+        rtc.advance_seconds(5);
+        assert_eq!(rtc.read(0x08), 5);
+    }
+
+    #[test]
+    fn trailer_layout() {
+        let mut rtc = rtc_with([1, 2, 3, 4, DAY_HIGH_BIT]);
+        latch(&mut rtc);
+        rtc.advance_seconds(1);
+        let timestamp = UNIX_EPOCH + Duration::from_secs(0x0102_0304_0506_0708);
+
+        let mut expected = [0; TRAILER_SIZE];
+        for (i, value) in [2, 2, 3, 4, 1, 1, 2, 3, 4, 1].into_iter().enumerate() {
+            expected[i * 4] = value;
+        }
+        expected[40..].copy_from_slice(&[0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(rtc.encode_trailer(timestamp), expected);
+    }
+
+    #[test]
+    fn trailer_round_trip() {
+        let mut rtc = rtc_with([59, 30, 12, 0xAB, DAY_HIGH_BIT | DAY_CARRY_BIT]);
+        latch(&mut rtc);
+        rtc.advance_seconds(90);
+        let timestamp = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let trailer = rtc.encode_trailer(timestamp);
+
+        let mut restored = RTC::new();
+        assert_eq!(restored.decode_trailer(&trailer), Some(timestamp));
+        assert_eq!(restored.encode_trailer(timestamp), trailer);
+        assert_eq!(restored.read(0x08), 59);
+        assert_eq!(latch(&mut restored), [29, 32, 12, 0xAB, 0x81]);
+    }
+
+    #[test]
+    fn short_trailer_has_32_bit_timestamp() {
+        let mut trailer = [0; SHORT_TRAILER_SIZE];
+        trailer[0] = 7;
+        trailer[20] = 6;
+        trailer[40..].copy_from_slice(&1_700_000_000u32.to_le_bytes());
+
+        let mut rtc = RTC::new();
+        assert_eq!(
+            rtc.decode_trailer(&trailer),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(rtc.read(0x08), 6);
+        assert_eq!(latch(&mut rtc)[SECONDS], 7);
+
+        assert_eq!(rtc.decode_trailer(&[0; 40]), None);
+    }
+
+    #[test]
+    fn latch_marks_modified() {
+        let mut rtc = RTC::new();
+        rtc.write_latch(0x00);
+        assert!(!rtc.take_modified());
+
+        rtc.write_latch(0x01);
+        assert!(rtc.take_modified());
+    }
+}
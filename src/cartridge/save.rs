@@ -0,0 +1,68 @@
+/*!
+Cartridges with a battery keep their RAM, and the state of their clock, while the Game Boy is turned off.
+This state is stored in a .sav file next to the ROM, in the raw format most other emulators use as well,
+so save files can be exchanged between them.
+https://gbdev.io/pandocs/MBC3.html
+*/
+
+use crate::cartridge::Cartridge;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub struct SaveFile {
+    path: PathBuf,
+}
+
+impl SaveFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> SaveFile {
+        SaveFile {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    // The save file of a ROM has the same name, with the extension replaced by .sav
+    pub fn for_rom<P: AsRef<Path>>(rom_path: P) -> SaveFile {
+        SaveFile::new(rom_path.as_ref().with_extension("sav"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Loads the save file into the cartridge and returns the time its clock was saved at, if it has one.
+    // A missing save file is not an error, the game simply starts without one
+    pub fn load(&self, cartridge: &mut Cartridge) -> std::io::Result<Option<SystemTime>> {
+        match std::fs::read(&self.path) {
+            Ok(data) => Ok(cartridge.load_save_data(&data)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Writes the save data to a temporary file first and then renames it over the save file, so a crash
+    // while writing never leaves a half written save file behind
+    pub fn write(&self, cartridge: &Cartridge) -> std::io::Result<()> {
+        let mut temporary_path = self.path.clone().into_os_string();
+        temporary_path.push(".tmp");
+
+        // The data has to reach the disk before the rename does, or a crash could still leave an empty file
+        let mut file = File::create(&temporary_path)?;
+        file.write_all(&cartridge.save_data())?;
+        file.sync_all()?;
+        std::fs::rename(&temporary_path, &self.path)?;
+
+        // The rename itself is only durable once the directory reached the disk as well, which can only be
+        // synced like a file on Unix
+        #[cfg(unix)]
+        {
+            let directory = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            File::open(directory)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
@@ -109,15 +109,16 @@ impl ALU {
             .write_u8(Register::F, flags.to_u8());
     }
 
+    // Adds the carry of the low byte, which add_register_16_low left in the C flag
     pub fn add_register_16_high(&mut self, register: Register) {
+        let mut flags = self.register_file.borrow().flags();
         let (result, bitwise_carry) = self.add_with_bitwise_carry(
             self.buffer,
             self.register_file.borrow().read_u16_high(register),
-            true,
+            flags.get_c(),
         );
         self.buffer = result;
 
-        let mut flags = self.register_file.borrow().flags();
         flags.set_n(false);
         flags.set_h(((bitwise_carry >> 3) & 0x1) == 0x1);
         flags.set_c(((bitwise_carry >> 7) & 0x1) == 0x1);
@@ -193,7 +194,7 @@ impl ALU {
 
         let mut flags = self.register_file.borrow().flags();
         flags.set_z(result == 0);
-        flags.set_n(false);
+        flags.set_n(true);
         flags.set_h(((bitwise_carry >> 3) & 0x1) == 0x1);
         self.register_file
             .borrow_mut()
@@ -267,7 +268,7 @@ impl ALU {
 
         if !flags.get_n() {
             // After an addition, adjust if (half-)carry occurred or if result is out of bounds
-            if flags.get_c() || self.buffer > 0x09 {
+            if flags.get_c() || self.buffer > 0x99 {
                 self.buffer = self.buffer.overflowing_add(0x60).0;
                 flags.set_c(true);
             }
@@ -311,7 +312,7 @@ impl ALU {
         } else {
             high_bit
         };
-        self.buffer = (self.buffer << 1) & replacement;
+        self.buffer = (self.buffer << 1) | replacement;
 
         flags.set_z(self.buffer == 0);
         flags.set_n(false);
@@ -331,7 +332,7 @@ impl ALU {
         } else {
             low_bit
         };
-        self.buffer = (self.buffer >> 1) & (replacement << 7);
+        self.buffer = (self.buffer >> 1) | (replacement << 7);
 
         flags.set_z(self.buffer == 0);
         flags.set_n(false);
@@ -359,7 +360,7 @@ impl ALU {
     pub fn shift_right(&mut self, arithmetic: bool) {
         let low_bit = self.buffer & 0x1;
         let replacement = if arithmetic { self.buffer >> 7 } else { 0 };
-        self.buffer = (self.buffer >> 1) & (replacement << 7);
+        self.buffer = (self.buffer >> 1) | (replacement << 7);
 
         let mut flags = self.register_file.borrow().flags();
         flags.set_z(self.buffer == 0);
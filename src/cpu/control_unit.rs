@@ -3,20 +3,83 @@ The control unit decodes the executed instructions and generates control signals
 the CPU core. It is also responsible for checking and dispatching interrupts.
 https://gekkio.fi/files/gb-docs/gbctr.pdf
 */
+
+use crate::cpu::register_file::{Register, RegisterFile};
+use crate::interrupts::{INTERRUPT_MASK, Interrupt, InterruptFlags};
+use std::cell::RefCell;
+use std::rc::Rc;
+
 pub struct ControlUnit {
+    register_file: Rc<RefCell<RegisterFile>>,
+    interrupt_flags: Rc<RefCell<InterruptFlags>>,
+
     ime: bool,
+    // EI only sets IME after the instruction following it
+    ime_scheduled: bool,
 }
 
 impl ControlUnit {
-    pub fn new() -> ControlUnit {
-        ControlUnit { ime: false }
+    pub fn new(
+        register_file: Rc<RefCell<RegisterFile>>,
+        interrupt_flags: Rc<RefCell<InterruptFlags>>,
+    ) -> ControlUnit {
+        ControlUnit {
+            register_file,
+            interrupt_flags,
+            ime: false,
+            ime_scheduled: false,
+        }
+    }
+
+    pub fn ime(&self) -> bool {
+        self.ime
     }
 
     pub fn enable_interrupts(&mut self) {
         self.ime = true;
     }
 
+    pub fn schedule_enable_interrupts(&mut self) {
+        self.ime_scheduled = true;
+    }
+
     pub fn disable_interrupts(&mut self) {
         self.ime = false;
+        self.ime_scheduled = false;
+    }
+
+    // The interrupts that are both requested in IF and enabled in IE, regardless of IME
+    pub fn pending_interrupts(&self) -> u8 {
+        self.register_file.borrow().read_u8(Register::IE)
+            & self.interrupt_flags.borrow().pending()
+            & INTERRUPT_MASK
+    }
+
+    // Checked at every instruction boundary. Returns whether an interrupt is dispatched instead of
+    // executing the next instruction, which also clears IME
+    pub fn check_interrupts(&mut self) -> bool {
+        let dispatch = self.ime && self.pending_interrupts() != 0;
+
+        if dispatch {
+            self.ime = false;
+        } else if self.ime_scheduled {
+            self.ime = true;
+        }
+        self.ime_scheduled = false;
+
+        dispatch
+    }
+
+    // Resolves the interrupt to service and acknowledges it in IF. This happens late in the dispatch
+    // sequence, so an interrupt requested or disabled in the meantime changes the outcome. If no
+    // interrupt is pending anymore, the dispatch is cancelled and jumps to 0x0000 instead
+    pub fn acknowledge_interrupt(&mut self) -> u16 {
+        match Interrupt::highest_priority(self.pending_interrupts()) {
+            Some(interrupt) => {
+                self.interrupt_flags.borrow_mut().acknowledge(interrupt);
+                interrupt.vector()
+            }
+            None => 0x0000,
+        }
     }
 }
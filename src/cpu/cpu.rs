@@ -1,59 +1,181 @@
-use crate::bus::Bus;
+use crate::bus::{Bus, BusCycle, Signal};
 use crate::cpu::alu::ALU;
 use crate::cpu::control_unit::ControlUnit;
 use crate::cpu::idu::IDU;
 use crate::cpu::instruction::Instruction;
 use crate::cpu::register_file::{Register, RegisterFile};
-use std::cell::{Ref, RefCell, RefMut};
+use crate::interrupts::InterruptFlags;
+use crate::memory::MMU;
+use crate::model::Model;
+use std::cell::{Ref, RefCell};
 use std::rc::Rc;
 
+// The number of M-cycles the CPU is paused for during a speed switch
+const SPEED_SWITCH_CYCLES: u16 = 2050;
+
 pub struct CPU {
     control_unit: ControlUnit,
     data_bus: Rc<RefCell<Bus<u8>>>,
     register_file: Rc<RefCell<RegisterFile>>,
     address_bus: Rc<RefCell<Bus<u16>>>,
+    interrupt_flags: Rc<RefCell<InterruptFlags>>,
     alu: ALU,
     idu: IDU,
+    mmu: MMU,
 
     current_instruction: Option<Instruction>,
     instruction_counter: u8,
     skip_pc_increment: bool,
+    halted: bool,
+    stopped: bool,
+    // The CPU is paused for a while after a speed switch
+    speed_switch_cycles: u16,
+
+    bus_trace: Option<Vec<BusCycle>>,
 }
 
 impl CPU {
-    pub fn new() -> CPU {
+    pub fn new(model: Model) -> CPU {
         let data_bus = Rc::new(RefCell::new(Bus::<u8>::new()));
         let address_bus = Rc::new(RefCell::new(Bus::<u16>::new()));
         let register_file = Rc::new(RefCell::new(RegisterFile::new(
             Rc::clone(&data_bus),
             Rc::clone(&address_bus),
         )));
+        let interrupt_flags = Rc::new(RefCell::new(InterruptFlags::new()));
 
         CPU {
-            control_unit: ControlUnit::new(),
+            control_unit: ControlUnit::new(Rc::clone(&register_file), Rc::clone(&interrupt_flags)),
             data_bus: Rc::clone(&data_bus),
             register_file: Rc::clone(&register_file),
             address_bus: Rc::clone(&address_bus),
+            interrupt_flags: Rc::clone(&interrupt_flags),
             alu: ALU::new(Rc::clone(&data_bus), Rc::clone(&register_file)),
             idu: IDU::new(Rc::clone(&address_bus), Rc::clone(&register_file)),
+            mmu: MMU::new(
+                Rc::clone(&data_bus),
+                Rc::clone(&address_bus),
+                Rc::clone(&register_file),
+                Rc::clone(&interrupt_flags),
+                model,
+            ),
             current_instruction: None,
             instruction_counter: 0,
             skip_pc_increment: false,
+            halted: false,
+            stopped: false,
+            speed_switch_cycles: 0,
+            bus_trace: None,
         }
     }
 
-    pub fn data_bus_mut(&mut self) -> RefMut<'_, Bus<u8>> {
-        self.data_bus.borrow_mut()
+    pub fn mmu(&self) -> &MMU {
+        &self.mmu
+    }
+
+    pub fn mmu_mut(&mut self) -> &mut MMU {
+        &mut self.mmu
+    }
+
+    // Puts the registers into the state the boot ROM leaves them in when it hands control over to the
+    // cartridge at 0x0100. Games tell the DMG and CGB apart by the value of A
+    pub fn skip_boot_rom(&mut self) {
+        let mut register_file = self.register_file.borrow_mut();
+        match self.mmu.model() {
+            Model::DMG => {
+                register_file.write_u8(Register::A, 0x01);
+                register_file.write_u8(Register::F, 0xB0);
+                register_file.write_u16(Register::BC, 0x0013);
+                register_file.write_u16(Register::DE, 0x00D8);
+                register_file.write_u16(Register::HL, 0x014D);
+            }
+            Model::CGB => {
+                register_file.write_u8(Register::A, 0x11);
+                register_file.write_u8(Register::F, 0x80);
+                register_file.write_u16(Register::BC, 0x0000);
+                register_file.write_u16(Register::DE, 0xFF56);
+                register_file.write_u16(Register::HL, 0x000D);
+            }
+        }
+        register_file.write_u16(Register::SP, 0xFFFE);
+        register_file.write_u16(Register::PC, 0x0100);
+
+        // The VBlank interrupt is requested while the boot ROM runs, but it never enables interrupts
+        self.interrupt_flags.borrow_mut().write(0xE1);
+        self.mmu.skip_boot_rom();
     }
 
     pub fn register_file(&self) -> Ref<'_, RegisterFile> {
         self.register_file.borrow()
     }
 
+    // Starts recording the state of the address and data bus at the end of every M-cycle
+    #[cfg(test)]
+    pub fn start_bus_trace(&mut self) {
+        self.bus_trace = Some(Vec::new());
+    }
+
+    // Stops recording bus cycles and returns the ones recorded so far
+    #[cfg(test)]
+    pub fn take_bus_trace(&mut self) -> Vec<BusCycle> {
+        self.bus_trace.take().unwrap_or_default()
+    }
+
+    // Asserts /RD, so the memory puts the data at the address on the address bus onto the data bus
+    fn memory_read(&mut self) {
+        self.address_bus.borrow_mut().assert_signal(Signal::Read);
+        self.mmu.respond();
+    }
+
+    // Asserts /WR, so the memory stores the data on the data bus at the address on the address bus
+    fn memory_write(&mut self) {
+        self.address_bus.borrow_mut().assert_signal(Signal::Write);
+        self.mmu.respond();
+    }
+
     pub fn clock_cycle(&mut self) {
-        // Decode the next instruction if we're not in the middle of one
+        // While halted, the CPU neither fetches nor executes anything until an enabled interrupt is requested.
+        // Noticing the interrupt takes this cycle, the next one either dispatches it or resumes execution.
+        // In STOP mode, the CPU waits for a button press instead, and after a speed switch it simply pauses
+        if self.halted || self.stopped || self.speed_switch_cycles > 0 {
+            if self.halted && self.control_unit.pending_interrupts() != 0 {
+                self.halted = false;
+            }
+            if self.stopped && self.mmu.joypad_line_low() {
+                self.stopped = false;
+            }
+            self.speed_switch_cycles = self.speed_switch_cycles.saturating_sub(1);
+
+            if let Some(bus_trace) = self.bus_trace.as_mut() {
+                bus_trace.push(BusCycle {
+                    signal: Signal::Idle,
+                    address: None,
+                    data: None,
+                });
+            }
+            // STOP mode also stops the system clock, only the cartridge keeps running on its own clock
+            if self.stopped {
+                self.mmu.clock_cartridge();
+            } else {
+                self.mmu.clock_cycle();
+            }
+            return;
+        }
+
+        // Offer the byte at PC on the data bus, so it can be used as an opcode or an immediate operand. It is
+        // only actually read at the end of the M-cycle, unless the instruction uses the buses itself
+        self.register_file.borrow().write_address_bus(Register::PC);
+        let pc = self.register_file.borrow().read_u16(Register::PC);
+        self.data_bus.borrow_mut().write(self.mmu.cpu_read(pc));
+
+        // Decode the next instruction if we're not in the middle of one. At an instruction boundary, a
+        // pending interrupt is dispatched instead of the fetched instruction
         if self.current_instruction.is_none() {
-            self.current_instruction = Some(self.decode());
+            self.current_instruction = if self.control_unit.check_interrupts() {
+                Some(Instruction::ISR())
+            } else {
+                Some(self.decode())
+            };
         }
 
         // Execute the current instruction
@@ -62,6 +184,26 @@ impl CPU {
             None => panic!("This should be impossible to happen"),
         }
 
+        // The byte at PC is fetched unless the instruction accessed memory or worked on an internal address
+        // instead. A finished instruction always fetches the next opcode, even if PC is not incremented
+        if self.address_bus.borrow().signal() == Signal::Idle {
+            if !self.skip_pc_increment || self.current_instruction.is_none() {
+                self.register_file.borrow().write_address_bus(Register::PC);
+                self.memory_read();
+            } else {
+                self.data_bus.borrow_mut().clear();
+            }
+        }
+
+        // Record the transaction this M-cycle ended with, before the IDU reuses the address bus
+        if let Some(bus_trace) = self.bus_trace.as_mut() {
+            bus_trace.push(BusCycle {
+                signal: self.address_bus.borrow().signal(),
+                address: self.address_bus.borrow().read(),
+                data: self.data_bus.borrow().read(),
+            });
+        }
+
         // If the instruction is fully executed, prepare the next one
         if self.current_instruction.is_none() {
             self.instruction_counter = 0;
@@ -78,6 +220,13 @@ impl CPU {
             self.idu.increment_into(Register::PC);
         }
         self.skip_pc_increment = false;
+
+        // The memory sees the address the IDU worked on during this M-cycle as well
+        if let Some(address) = self.idu.take_active_address() {
+            self.mmu.idu_activity(address);
+        }
+
+        self.mmu.clock_cycle();
     }
 
     pub fn decode(&mut self) -> Instruction {
@@ -110,11 +259,11 @@ impl CPU {
                     Instruction::CCF()
                 } else if instruction_body_1 == 6 && instruction_body_2 == 7 {
                     Instruction::SCF()
-                } else if instruction_body_1 < 6 && instruction_body_2 == 4 {
+                } else if instruction_body_1 != 6 && instruction_body_2 == 4 {
                     Instruction::INC(Register::data_register(instruction_body_1))
                 } else if instruction_body_1 == 6 && instruction_body_2 == 4 {
                     Instruction::INC_HL()
-                } else if instruction_body_1 < 6 && instruction_body_2 == 5 {
+                } else if instruction_body_1 != 6 && instruction_body_2 == 5 {
                     Instruction::DEC(Register::data_register(instruction_body_1))
                 } else if instruction_body_1 == 6 && instruction_body_2 == 5 {
                     Instruction::DEC_HL()
@@ -158,7 +307,9 @@ impl CPU {
                 }
             }
             0b01 => {
-                if instruction_body_1 < 6 && instruction_body_2 < 6 {
+                if instruction_body_1 == 6 && instruction_body_2 == 6 {
+                    Instruction::HALT()
+                } else if instruction_body_1 != 6 && instruction_body_2 != 6 {
                     Instruction::LDR(
                         Register::data_register(instruction_body_1),
                         Register::data_register(instruction_body_2),
@@ -167,42 +318,40 @@ impl CPU {
                     Instruction::LD(Register::data_register(instruction_body_1))
                 } else if instruction_body_1 == 6 {
                     Instruction::LDM(Register::data_register(instruction_body_2))
-                } else if instruction_body_1 == 6 && instruction_body_2 == 6 {
-                    Instruction::HALT()
                 } else {
                     panic!("Unimplemented or invalid instruction {instruction}");
                 }
             }
             0b10 => {
-                if instruction_body_1 == 4 && instruction_body_2 < 6 {
+                if instruction_body_1 == 4 && instruction_body_2 != 6 {
                     Instruction::AND(Register::data_register(instruction_body_2))
                 } else if instruction_body_1 == 4 && instruction_body_2 == 6 {
                     Instruction::AND_HL()
-                } else if instruction_body_1 == 6 && instruction_body_2 < 6 {
+                } else if instruction_body_1 == 6 && instruction_body_2 != 6 {
                     Instruction::OR(Register::data_register(instruction_body_2))
                 } else if instruction_body_1 == 6 && instruction_body_2 == 6 {
                     Instruction::OR_HL()
-                } else if instruction_body_1 == 5 && instruction_body_2 < 6 {
+                } else if instruction_body_1 == 5 && instruction_body_2 != 6 {
                     Instruction::XOR(Register::data_register(instruction_body_2))
                 } else if instruction_body_1 == 5 && instruction_body_2 == 6 {
                     Instruction::XOR_HL()
-                } else if instruction_body_1 == 7 && instruction_body_2 < 6 {
+                } else if instruction_body_1 == 7 && instruction_body_2 != 6 {
                     Instruction::CP(Register::data_register(instruction_body_2))
                 } else if instruction_body_1 == 7 && instruction_body_2 == 6 {
                     Instruction::CP_HL()
                 } else if instruction_body_1 == 1 && instruction_body_2 == 6 {
                     Instruction::ADC_HL()
-                } else if instruction_body_1 == 1 && instruction_body_2 < 6 {
+                } else if instruction_body_1 == 1 && instruction_body_2 != 6 {
                     Instruction::ADC(Register::data_register(instruction_body_2))
-                } else if instruction_body_1 == 0 && instruction_body_2 < 6 {
+                } else if instruction_body_1 == 0 && instruction_body_2 != 6 {
                     Instruction::ADD(Register::data_register(instruction_body_2))
                 } else if instruction_body_1 == 0 && instruction_body_2 == 6 {
                     Instruction::ADD_HL()
                 } else if instruction_body_1 == 3 && instruction_body_2 == 6 {
                     Instruction::SBC_HL()
-                } else if instruction_body_1 == 3 && instruction_body_2 < 6 {
+                } else if instruction_body_1 == 3 && instruction_body_2 != 6 {
                     Instruction::SBC(Register::data_register(instruction_body_2))
-                } else if instruction_body_1 == 2 && instruction_body_2 < 6 {
+                } else if instruction_body_1 == 2 && instruction_body_2 != 6 {
                     Instruction::SUB(Register::data_register(instruction_body_2))
                 } else if instruction_body_1 == 2 && instruction_body_2 == 6 {
                     Instruction::SUB_HL()
@@ -234,9 +383,9 @@ impl CPU {
                 } else if instruction_body_1 == 7 && instruction_body_2 == 0 {
                     Instruction::LD_SPE()
                 } else if (instruction_body_1 & 0x1) == 0 && instruction_body_2 == 1 {
-                    Instruction::POP(Register::register_pair(instruction_body_1 >> 1))
+                    Instruction::POP(Register::stack_register_pair(instruction_body_1 >> 1))
                 } else if (instruction_body_1 & 0x1) == 0 && instruction_body_2 == 5 {
-                    Instruction::PUSH(Register::register_pair(instruction_body_1 >> 1))
+                    Instruction::PUSH(Register::stack_register_pair(instruction_body_1 >> 1))
                 } else if instruction_body_1 == 7 && instruction_body_2 == 1 {
                     Instruction::LD_SP_HL()
                 } else if instruction_body_1 == 7 && instruction_body_2 == 2 {
@@ -333,19 +482,19 @@ impl CPU {
             Instruction::SRA_HL()
         } else if instruction_body_1 == 0x2 && instruction_body_2 == 0xF {
             Instruction::SRA(Register::A)
-        } else if instruction_body_1 == 0x3 && instruction_body_2 < 0xE {
-            Instruction::SRL(Register::data_register(instruction_body_2))
-        } else if instruction_body_1 == 0x3 && instruction_body_2 == 0xE {
-            Instruction::SRL_HL()
-        } else if instruction_body_1 == 0x3 && instruction_body_2 == 0xF {
-            Instruction::SRL(Register::A)
         } else if instruction_body_1 == 0x3 && instruction_body_2 < 0x6 {
             Instruction::SWAP(Register::data_register(instruction_body_2))
         } else if instruction_body_1 == 0x3 && instruction_body_2 == 0x6 {
             Instruction::SWAP_HL()
         } else if instruction_body_1 == 0x3 && instruction_body_2 == 0x7 {
             Instruction::SWAP(Register::A)
-        } else if instruction_body_1 >= 0x4 {
+        } else if instruction_body_1 == 0x3 && instruction_body_2 < 0xE {
+            Instruction::SRL(Register::data_register(instruction_body_2 - 0x8))
+        } else if instruction_body_1 == 0x3 && instruction_body_2 == 0xE {
+            Instruction::SRL_HL()
+        } else if instruction_body_1 == 0x3 && instruction_body_2 == 0xF {
+            Instruction::SRL(Register::A)
+        } else if instruction_body_1 < 0x8 {
             let bit_idx = 2 * (instruction_body_1 - 0x4) + ((instruction_body_2 >= 0x8) as u8);
             if (instruction_body_2 & 0x7) < 0x6 {
                 Instruction::BIT(bit_idx, Register::data_register(instruction_body_2 & 0x7))
@@ -356,7 +505,7 @@ impl CPU {
             } else {
                 panic!("Unimplemented or invalid instruction {instruction}");
             }
-        } else if instruction_body_1 >= 0x8 {
+        } else if instruction_body_1 < 0xC {
             let bit_idx = 2 * (instruction_body_1 - 0x8) + ((instruction_body_2 >= 0x8) as u8);
             if (instruction_body_2 & 0x7) < 0x6 {
                 Instruction::RES(bit_idx, Register::data_register(instruction_body_2 & 0x7))
@@ -367,7 +516,7 @@ impl CPU {
             } else {
                 panic!("Unimplemented or invalid instruction {instruction}");
             }
-        } else if instruction_body_1 >= 0xC {
+        } else {
             let bit_idx = 2 * (instruction_body_1 - 0xC) + ((instruction_body_2 >= 0x8) as u8);
             if (instruction_body_2 & 0x7) < 0x6 {
                 Instruction::SET(bit_idx, Register::data_register(instruction_body_2 & 0x7))
@@ -378,18 +527,14 @@ impl CPU {
             } else {
                 panic!("Unimplemented or invalid instruction {instruction}");
             }
-        } else {
-            panic!("Unimplemented or invalid instruction {instruction}");
         }
     }
 
     pub fn execute(&mut self, current_instruction: Instruction) {
-        println!("Executing {current_instruction:?}");
-
         match current_instruction {
             Instruction::LDR(r, r_) => {
-                self.alu.read_data_register(r);
-                self.alu.write_data_register(r_);
+                self.alu.read_data_register(r_);
+                self.alu.write_data_register(r);
                 self.current_instruction = None;
             }
 
@@ -411,7 +556,7 @@ impl CPU {
             Instruction::LD(r) => match self.instruction_counter {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::HL);
-                    // TODO send read signal to the memory (needs to be synchronous)
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -431,7 +576,7 @@ impl CPU {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::HL);
                     self.register_file.borrow().write_data_bus(r);
-                    // TODO send write signal to the memory
+                    self.memory_write();
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -452,7 +597,7 @@ impl CPU {
                 1 => {
                     self.register_file.borrow().write_address_bus(Register::HL);
                     self.register_file.borrow().write_data_bus(Register::Z);
-                    // TODO send write signal to the memory
+                    self.memory_write();
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -468,7 +613,7 @@ impl CPU {
             Instruction::LDA_BC() => match self.instruction_counter {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::BC);
-                    // TODO send read signal to the memory (needs to be synchronous)
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -487,7 +632,7 @@ impl CPU {
             Instruction::LDA_DE() => match self.instruction_counter {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::DE);
-                    // TODO send read signal to the memory (needs to be synchronous)
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -507,7 +652,7 @@ impl CPU {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::BC);
                     self.register_file.borrow().write_data_bus(Register::A);
-                    // TODO send write signal to the memory
+                    self.memory_write();
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -524,7 +669,7 @@ impl CPU {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::DE);
                     self.register_file.borrow().write_data_bus(Register::A);
-                    // TODO send write signal to the memory
+                    self.memory_write();
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -548,6 +693,9 @@ impl CPU {
                 }
                 2 => {
                     self.register_file.borrow().write_address_bus(Register::WZ);
+                    self.memory_read();
+                    self.register_file.borrow_mut().read_data_bus(Register::Z);
+
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -573,7 +721,7 @@ impl CPU {
                 2 => {
                     self.register_file.borrow().write_address_bus(Register::WZ);
                     self.register_file.borrow().write_data_bus(Register::A);
-                    // TODO send write signal to the memory
+                    self.memory_write();
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -591,7 +739,7 @@ impl CPU {
                     let c = self.register_file.borrow().read_u8(Register::C);
                     let address = 0xFF00 | (c as u16);
                     self.address_bus.borrow_mut().write(address);
-                    // TODO send read signal to the memory (needs to be synchronous)
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -613,7 +761,7 @@ impl CPU {
                     let address = 0xFF00 | (c as u16);
                     self.address_bus.borrow_mut().write(address);
                     self.register_file.borrow_mut().write_data_bus(Register::A);
-                    // TODO send write signal to the memory
+                    self.memory_write();
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -635,7 +783,7 @@ impl CPU {
                     let c = self.register_file.borrow().read_u8(Register::Z);
                     let address = 0xFF00 | (c as u16);
                     self.address_bus.borrow_mut().write(address);
-                    // TODO send read signal to the memory (needs to be synchronous)
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -661,7 +809,7 @@ impl CPU {
                     let address = 0xFF00 | (c as u16);
                     self.address_bus.borrow_mut().write(address);
                     self.register_file.borrow_mut().write_data_bus(Register::A);
-                    // TODO send write signal to the memory
+                    self.memory_write();
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -677,7 +825,7 @@ impl CPU {
             Instruction::LDH_HLM() => match self.instruction_counter {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::HL);
-                    // TODO send read signal to the memory (needs to be synchronous)
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.idu.decrement_into(Register::HL);
@@ -701,6 +849,7 @@ impl CPU {
                         .borrow_mut()
                         .write_address_bus(Register::HL);
                     self.register_file.borrow_mut().write_data_bus(Register::A);
+                    self.memory_write();
 
                     self.idu.decrement_into(Register::HL);
 
@@ -719,7 +868,7 @@ impl CPU {
             Instruction::LDH_HLP() => match self.instruction_counter {
                 0 => {
                     self.register_file.borrow().write_address_bus(Register::HL);
-                    // TODO send read signal to the memory (needs to be synchronous)
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.idu.increment_into(Register::HL);
@@ -743,6 +892,7 @@ impl CPU {
                         .borrow_mut()
                         .write_address_bus(Register::HL);
                     self.register_file.borrow_mut().write_data_bus(Register::A);
+                    self.memory_write();
 
                     self.idu.increment_into(Register::HL);
 
@@ -793,6 +943,7 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow_mut().read_u16_low(Register::SP));
+                    self.memory_write();
 
                     self.idu.increment_into(Register::WZ);
 
@@ -805,7 +956,8 @@ impl CPU {
                         .write_address_bus(Register::WZ);
                     self.data_bus
                         .borrow_mut()
-                        .write(self.register_file.borrow_mut().read_u16_low(Register::SP));
+                        .write(self.register_file.borrow_mut().read_u16_high(Register::SP));
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -855,7 +1007,7 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow_mut().read_u16_high(r));
-                    // TODO send memory write signal
+                    self.memory_write();
 
                     self.idu.decrement_into(Register::SP);
 
@@ -869,7 +1021,7 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow_mut().read_u16_low(r));
-                    // TODO send memory write signal
+                    self.memory_write();
 
                     // This seems like a no-op, though it's in the datasheet so we'll leave it here for completeness purposes
                     self.idu.write_into(Register::SP);
@@ -891,7 +1043,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.idu.increment_into(Register::SP);
@@ -903,7 +1055,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::W);
 
                     self.idu.increment_into(Register::SP);
@@ -912,9 +1064,12 @@ impl CPU {
                     self.instruction_counter += 1;
                 }
                 2 => {
-                    self.register_file
-                        .borrow_mut()
-                        .write_u16(r, self.register_file.borrow_mut().read_u16(Register::WZ));
+                    let mut wz = self.register_file.borrow().read_u16(Register::WZ);
+                    // The lower 4 bits of F don't exist and always read as 0
+                    if matches!(r, Register::AF) {
+                        wz &= 0xFFF0;
+                    }
+                    self.register_file.borrow_mut().write_u16(r, wz);
                     self.current_instruction = None;
                 }
                 _ => {
@@ -959,7 +1114,7 @@ impl CPU {
 
             Instruction::ADD_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO Send read signal to the memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -982,7 +1137,7 @@ impl CPU {
 
             Instruction::ADC_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO Send read signal to the memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -1005,7 +1160,7 @@ impl CPU {
 
             Instruction::SUB_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO Send read signal to the memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -1028,7 +1183,7 @@ impl CPU {
 
             Instruction::SBC_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO Send read signal to the memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -1043,14 +1198,14 @@ impl CPU {
 
             Instruction::CP(r) => {
                 self.alu.read_data_register(Register::A);
-                self.alu.sub_register(r, true);
+                self.alu.sub_register(r, false);
 
                 self.current_instruction = None;
             }
 
             Instruction::CP_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO Send read signal to the memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -1076,17 +1231,21 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 1 => {
+                    self.register_file
+                        .borrow_mut()
+                        .write_address_bus(Register::HL);
+
                     self.alu.read_data_register(Register::Z);
                     self.alu.increment();
                     self.alu.write_data_bus();
-                    // TODO send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1113,17 +1272,21 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 1 => {
+                    self.register_file
+                        .borrow_mut()
+                        .write_address_bus(Register::HL);
+
                     self.alu.read_data_register(Register::Z);
                     self.alu.decrement();
                     self.alu.write_data_bus();
-                    // TODO send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1147,7 +1310,7 @@ impl CPU {
 
             Instruction::AND_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO send read signal to memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -1157,7 +1320,6 @@ impl CPU {
             Instruction::ANDI() => {
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
-                self.skip_pc_increment = true;
                 self.current_instruction = Some(Instruction::AND(Register::Z));
             }
 
@@ -1171,7 +1333,7 @@ impl CPU {
 
             Instruction::OR_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO send read signal to memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -1181,7 +1343,6 @@ impl CPU {
             Instruction::ORI() => {
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
-                self.skip_pc_increment = true;
                 self.current_instruction = Some(Instruction::OR(Register::Z));
             }
 
@@ -1195,7 +1356,7 @@ impl CPU {
 
             Instruction::XOR_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
-                // TODO send read signal to memory
+                self.memory_read();
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                 self.skip_pc_increment = true;
@@ -1205,7 +1366,6 @@ impl CPU {
             Instruction::XORI() => {
                 self.register_file.borrow_mut().read_data_bus(Register::Z);
 
-                self.skip_pc_increment = true;
                 self.current_instruction = Some(Instruction::XOR(Register::Z));
             }
 
@@ -1283,9 +1443,9 @@ impl CPU {
                     self.instruction_counter += 1;
                 }
                 1 => {
-                    self.alu.read_register_pair_low(Register::HL);
+                    self.alu.read_register_pair_high(Register::HL);
                     self.alu.add_register_16_high(r);
-                    self.alu.write_register_pair_low(Register::HL);
+                    self.alu.write_register_pair_high(Register::HL);
 
                     self.current_instruction = None;
                 }
@@ -1302,9 +1462,11 @@ impl CPU {
                 1 => {
                     self.address_bus.borrow_mut().write(0x0000);
 
+                    // The offset stays in Z for the sign extension of the high byte, so the result goes
+                    // straight to SP, whose high byte is only read before it is written
                     self.alu.read_data_register(Register::Z);
                     self.alu.addi_register_16_low(Register::SP);
-                    self.alu.write_register_pair_low(Register::WZ);
+                    self.alu.write_register_pair_low(Register::SP);
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1314,17 +1476,12 @@ impl CPU {
 
                     self.alu.read_data_register(Register::Z);
                     self.alu.addi_register_16_high(Register::SP);
-                    self.alu.write_register_pair_high(Register::WZ);
+                    self.alu.write_register_pair_high(Register::SP);
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 3 => {
-                    self.register_file.borrow_mut().write_u16(
-                        Register::SP,
-                        self.register_file.borrow_mut().read_u16(Register::WZ),
-                    );
-
                     self.current_instruction = None;
                 }
                 _ => {
@@ -1383,7 +1540,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1397,7 +1554,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.rotate_left(false);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1424,7 +1581,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1438,7 +1595,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.rotate_right(false);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1465,7 +1622,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1479,7 +1636,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.rotate_left(true);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1506,7 +1663,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1520,7 +1677,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.rotate_right(true);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1547,7 +1704,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1561,7 +1718,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.shift_left();
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1588,7 +1745,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1602,7 +1759,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.shift_right(true);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1629,7 +1786,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1643,7 +1800,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.shift_right(false);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1670,7 +1827,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1684,7 +1841,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.swap();
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1710,7 +1867,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1734,6 +1891,7 @@ impl CPU {
             Instruction::RES(bit_idx, r) => {
                 self.alu.read_data_register(r);
                 self.alu.reset_bit(bit_idx);
+                self.alu.write_data_register(r);
 
                 self.current_instruction = None;
             }
@@ -1743,7 +1901,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1757,7 +1915,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.reset_bit(bit_idx);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1774,6 +1932,7 @@ impl CPU {
             Instruction::SET(bit_idx, r) => {
                 self.alu.read_data_register(r);
                 self.alu.set_bit(bit_idx);
+                self.alu.write_data_register(r);
 
                 self.current_instruction = None;
             }
@@ -1783,7 +1942,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::HL);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.skip_pc_increment = true;
@@ -1797,7 +1956,7 @@ impl CPU {
                     self.alu.read_data_register(Register::Z);
                     self.alu.set_bit(bit_idx);
                     self.alu.write_data_bus();
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -1822,10 +1981,8 @@ impl CPU {
                 }
                 2 => {
                     self.address_bus.borrow_mut().write(0x0000);
-                    self.register_file.borrow_mut().write_u16(
-                        Register::PC,
-                        self.register_file.borrow().read_u16(Register::WZ),
-                    );
+                    let wz = self.register_file.borrow().read_u16(Register::WZ);
+                    self.register_file.borrow_mut().write_u16(Register::PC, wz);
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
@@ -1839,6 +1996,8 @@ impl CPU {
 
             Instruction::JP_HL() => {
                 self.register_file.borrow().write_address_bus(Register::HL);
+                // The next opcode is fetched from the jump target
+                self.memory_read();
                 self.idu.increment_into(Register::PC);
 
                 self.skip_pc_increment = true;
@@ -1862,11 +2021,10 @@ impl CPU {
                     if comparision {
                         self.address_bus.borrow_mut().write(0x0000);
 
-                        self.register_file.borrow_mut().write_u16(
-                            Register::PC,
-                            self.register_file.borrow().read_u16(Register::WZ),
-                        );
+                        let wz = self.register_file.borrow().read_u16(Register::WZ);
+                        self.register_file.borrow_mut().write_u16(Register::PC, wz);
 
+                        self.skip_pc_increment = true;
                         self.instruction_counter += 1;
                     } else {
                         self.current_instruction = None;
@@ -1901,6 +2059,8 @@ impl CPU {
                 }
                 2 => {
                     self.register_file.borrow().write_address_bus(Register::WZ);
+                    // The next opcode is fetched from the jump target
+                    self.memory_read();
                     self.idu.increment_into(Register::PC);
 
                     self.skip_pc_increment = true;
@@ -1944,6 +2104,8 @@ impl CPU {
                 }
                 3 => {
                     self.register_file.borrow().write_address_bus(Register::WZ);
+                    // The next opcode is fetched from the jump target
+                    self.memory_read();
                     self.idu.increment_into(Register::PC);
 
                     self.skip_pc_increment = true;
@@ -1969,6 +2131,7 @@ impl CPU {
                         .write_address_bus(Register::SP);
                     self.idu.decrement_into(Register::SP);
 
+                    self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 3 => {
@@ -1978,10 +2141,11 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow().read_u16_high(Register::PC));
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.idu.decrement_into(Register::SP);
 
+                    self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 4 => {
@@ -1991,15 +2155,14 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow().read_u16_low(Register::PC));
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.idu.write_into(Register::SP);
 
-                    self.register_file.borrow_mut().write_u16(
-                        Register::SP,
-                        self.register_file.borrow().read_u16(Register::WZ),
-                    );
+                    let wz = self.register_file.borrow().read_u16(Register::WZ);
+                    self.register_file.borrow_mut().write_u16(Register::PC, wz);
 
+                    self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 5 => {
@@ -2039,6 +2202,7 @@ impl CPU {
                         .write_address_bus(Register::SP);
                     self.idu.decrement_into(Register::SP);
 
+                    self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 4 => {
@@ -2048,10 +2212,11 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow().read_u16_high(Register::PC));
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.idu.decrement_into(Register::SP);
 
+                    self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 5 => {
@@ -2061,15 +2226,14 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow().read_u16_low(Register::PC));
-                    // TODO Send write signal to memory
+                    self.memory_write();
 
                     self.idu.write_into(Register::SP);
 
-                    self.register_file.borrow_mut().write_u16(
-                        Register::SP,
-                        self.register_file.borrow().read_u16(Register::WZ),
-                    );
+                    let wz = self.register_file.borrow().read_u16(Register::WZ);
+                    self.register_file.borrow_mut().write_u16(Register::PC, wz);
 
+                    self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 6 => {
@@ -2086,7 +2250,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.idu.increment_into(Register::SP);
@@ -2098,7 +2262,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::W);
 
                     self.idu.increment_into(Register::SP);
@@ -2109,10 +2273,8 @@ impl CPU {
                 2 => {
                     self.address_bus.borrow_mut().write(0x0000);
 
-                    self.register_file.borrow_mut().write_u16(
-                        Register::PC,
-                        self.register_file.borrow().read_u16(Register::WZ),
-                    );
+                    let wz = self.register_file.borrow().read_u16(Register::WZ);
+                    self.register_file.borrow_mut().write_u16(Register::PC, wz);
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -2145,7 +2307,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.idu.increment_into(Register::SP);
@@ -2157,7 +2319,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::W);
 
                     self.idu.increment_into(Register::SP);
@@ -2168,10 +2330,8 @@ impl CPU {
                 3 => {
                     self.address_bus.borrow_mut().write(0x0000);
 
-                    self.register_file.borrow_mut().write_u16(
-                        Register::PC,
-                        self.register_file.borrow().read_u16(Register::WZ),
-                    );
+                    let wz = self.register_file.borrow().read_u16(Register::WZ);
+                    self.register_file.borrow_mut().write_u16(Register::PC, wz);
 
                     self.skip_pc_increment = true;
                     self.instruction_counter += 1;
@@ -2190,7 +2350,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::Z);
 
                     self.idu.increment_into(Register::SP);
@@ -2202,7 +2362,7 @@ impl CPU {
                     self.register_file
                         .borrow_mut()
                         .write_address_bus(Register::SP);
-                    // TODO Send read signal to memory
+                    self.memory_read();
                     self.register_file.borrow_mut().read_data_bus(Register::W);
 
                     self.idu.increment_into(Register::SP);
@@ -2213,10 +2373,8 @@ impl CPU {
                 2 => {
                     self.address_bus.borrow_mut().write(0x0000);
 
-                    self.register_file.borrow_mut().write_u16(
-                        Register::PC,
-                        self.register_file.borrow().read_u16(Register::WZ),
-                    );
+                    let wz = self.register_file.borrow().read_u16(Register::WZ);
+                    self.register_file.borrow_mut().write_u16(Register::PC, wz);
 
                     self.control_unit.enable_interrupts();
 
@@ -2249,7 +2407,7 @@ impl CPU {
                     self.data_bus
                         .borrow_mut()
                         .write(self.register_file.borrow().read_u16_high(Register::PC));
-                    // TODO send write signal to memory
+                    self.memory_write();
 
                     self.idu.decrement_into(Register::SP);
 
@@ -2262,8 +2420,8 @@ impl CPU {
                         .write_address_bus(Register::SP);
                     self.data_bus
                         .borrow_mut()
-                        .write(self.register_file.borrow().read_u16_high(Register::PC));
-                    // TODO send write signal to memory
+                        .write(self.register_file.borrow().read_u16_low(Register::PC));
+                    self.memory_write();
 
                     self.idu.write_into(Register::SP);
 
@@ -2271,9 +2429,76 @@ impl CPU {
                         .borrow_mut()
                         .write_u16(Register::PC, address as u16);
 
+                    self.skip_pc_increment = true;
+                    self.instruction_counter += 1;
+                }
+                3 => {
+                    // This clock cycle is necessary since the address and data bus were busy in the previous cycle
+                    self.current_instruction = None;
+                }
+                _ => {
+                    panic!("Unimplemented instruction counter for instruction");
+                }
+            },
+
+            Instruction::ISR() => match self.instruction_counter {
+                0 => {
+                    // The instruction that was already fetched is discarded, so PC has to point at it again
+                    self.register_file
+                        .borrow_mut()
+                        .write_address_bus(Register::PC);
+                    self.idu.decrement_into(Register::PC);
+
+                    self.skip_pc_increment = true;
+                    self.instruction_counter += 1;
+                }
+                1 => {
+                    self.register_file
+                        .borrow_mut()
+                        .write_address_bus(Register::SP);
+                    self.idu.decrement_into(Register::SP);
+
+                    self.skip_pc_increment = true;
+                    self.instruction_counter += 1;
+                }
+                2 => {
+                    self.register_file
+                        .borrow_mut()
+                        .write_address_bus(Register::SP);
+                    self.data_bus
+                        .borrow_mut()
+                        .write(self.register_file.borrow().read_u16_high(Register::PC));
+                    self.memory_write();
+
+                    self.idu.decrement_into(Register::SP);
+
+                    self.skip_pc_increment = true;
                     self.instruction_counter += 1;
                 }
                 3 => {
+                    // The interrupt is resolved after the upper byte of PC was pushed, but before the lower
+                    // byte is. If SP pointed at 0x0000, the upper byte landed in IE and may have disabled
+                    // the interrupt being dispatched, while the lower byte landing in IE comes too late
+                    let vector = self.control_unit.acknowledge_interrupt();
+
+                    self.register_file
+                        .borrow_mut()
+                        .write_address_bus(Register::SP);
+                    self.data_bus
+                        .borrow_mut()
+                        .write(self.register_file.borrow().read_u16_low(Register::PC));
+                    self.memory_write();
+
+                    self.idu.write_into(Register::SP);
+
+                    self.register_file
+                        .borrow_mut()
+                        .write_u16(Register::PC, vector);
+
+                    self.skip_pc_increment = true;
+                    self.instruction_counter += 1;
+                }
+                4 => {
                     // This clock cycle is necessary since the address and data bus were busy in the previous cycle
                     self.current_instruction = None;
                 }
@@ -2282,7 +2507,40 @@ impl CPU {
                 }
             },
 
-            Instruction::HALT() | Instruction::STOP() => { /* Do nothing */ }
+            Instruction::HALT() => {
+                // The following instruction was already fetched in this cycle. If IME is not set but an
+                // interrupt is pending, HALT exits immediately and PC fails to increment, so the byte after
+                // HALT is read twice
+                if !self.control_unit.ime() && self.control_unit.pending_interrupts() != 0 {
+                    self.skip_pc_increment = true;
+                } else {
+                    self.halted = true;
+                }
+                self.current_instruction = None;
+            }
+
+            Instruction::STOP() => match self.instruction_counter {
+                0 => {
+                    // The byte following the opcode is ignored, but still skipped
+                    self.register_file.borrow_mut().read_data_bus(Register::Z);
+                    self.instruction_counter += 1;
+                }
+                1 => {
+                    self.mmu.reset_div();
+
+                    // On the CGB, an armed speed switch is performed instead of entering STOP mode
+                    if self.mmu.model() == Model::CGB && self.mmu.speed_switch_armed() {
+                        self.mmu.switch_speed();
+                        self.speed_switch_cycles = SPEED_SWITCH_CYCLES;
+                    } else {
+                        self.stopped = true;
+                    }
+                    self.current_instruction = None;
+                }
+                _ => {
+                    panic!("Unimplemented instruction counter for instruction");
+                }
+            },
 
             Instruction::DI() => {
                 self.control_unit.disable_interrupts();
@@ -2290,7 +2548,7 @@ impl CPU {
             }
 
             Instruction::EI() => {
-                self.control_unit.enable_interrupts();
+                self.control_unit.schedule_enable_interrupts();
                 self.current_instruction = None;
             }
 
@@ -2300,3 +2558,295 @@ impl CPU {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    // Runs the code from the entry point of a ROM until the CPU halts
+    fn run(code: &[(u16, &[u8])]) -> CPU {
+        let mut cpu = CPU::new(Model::DMG);
+        cpu.mmu_mut().insert_cartridge(Cartridge::with_code(code));
+        cpu.skip_boot_rom();
+
+        for _ in 0..1000 {
+            if cpu.halted {
+                return cpu;
+            }
+            cpu.clock_cycle();
+        }
+        panic!("The CPU did not halt");
+    }
+
+    // Runs the code until the opcode at the address was fetched, then records the bus for the M-cycles
+    // the instruction takes, up to and including the fetch of the next opcode
+    fn trace(code: &[(u16, &[u8])], address: u16, cycles: usize) -> Vec<BusCycle> {
+        let mut cpu = CPU::new(Model::DMG);
+        cpu.mmu_mut().insert_cartridge(Cartridge::with_code(code));
+        cpu.skip_boot_rom();
+
+        while cpu.current_instruction.is_some()
+            || cpu.register_file().read_u16(Register::PC) != address + 1
+        {
+            cpu.clock_cycle();
+        }
+
+        cpu.start_bus_trace();
+        for _ in 0..cycles {
+            cpu.clock_cycle();
+        }
+        cpu.take_bus_trace()
+    }
+
+    fn cycle(signal: Signal, address: u16, data: Option<u8>) -> BusCycle {
+        BusCycle {
+            signal,
+            address: Some(address),
+            data,
+        }
+    }
+
+    // LD HL,0xC000; LD SP,0xD000; LD (HL),0x5A; PUSH BC; CALL 0x0200; HALT, and HALT at 0x0200
+    const TRACED_CODE: [(u16, &[u8]); 2] = [
+        (
+            0x0150,
+            &[
+                0x21, 0x00, 0xC0, 0x31, 0x00, 0xD0, 0x36, 0x5A, 0xC5, 0xCD, 0x00, 0x02, 0x76,
+            ],
+        ),
+        (0x0200, &[0x76]),
+    ];
+
+    #[test]
+    fn ld_hl_immediate_bus_accesses() {
+        assert_eq!(
+            trace(&TRACED_CODE, 0x0156, 3),
+            [
+                cycle(Signal::Read, 0x0157, Some(0x5A)),
+                cycle(Signal::Write, 0xC000, Some(0x5A)),
+                cycle(Signal::Read, 0x0158, Some(0xC5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn push_bus_accesses() {
+        // BC is 0x0013 after the boot ROM
+        assert_eq!(
+            trace(&TRACED_CODE, 0x0158, 4),
+            [
+                cycle(Signal::Idle, 0xD000, None),
+                cycle(Signal::Write, 0xCFFF, Some(0x00)),
+                cycle(Signal::Write, 0xCFFE, Some(0x13)),
+                cycle(Signal::Read, 0x0159, Some(0xCD)),
+            ]
+        );
+    }
+
+    #[test]
+    fn call_bus_accesses() {
+        assert_eq!(
+            trace(&TRACED_CODE, 0x0159, 6),
+            [
+                cycle(Signal::Read, 0x015A, Some(0x00)),
+                cycle(Signal::Read, 0x015B, Some(0x02)),
+                cycle(Signal::Idle, 0xCFFE, None),
+                cycle(Signal::Write, 0xCFFD, Some(0x01)),
+                cycle(Signal::Write, 0xCFFC, Some(0x5C)),
+                cycle(Signal::Read, 0x0200, Some(0x76)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rst_pushes_pc_and_jumps_to_vector() {
+        // LD SP,0xD000; RST 0x08; HALT, and LD B,0x42; RET at 0x0008
+        let cpu = run(&[
+            (0x0150, &[0x31, 0x00, 0xD0, 0xCF, 0x76]),
+            (0x0008, &[0x06, 0x42, 0xC9]),
+        ]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0x42);
+        assert_eq!(cpu.register_file().read_u16(Register::SP), 0xD000);
+        assert_eq!(cpu.mmu().read(0xCFFF), 0x01);
+        assert_eq!(cpu.mmu().read(0xCFFE), 0x54);
+    }
+
+    #[test]
+    fn call_pushes_pc_and_jumps_to_target() {
+        // LD SP,0xD000; CALL 0x0200; LD C,0x11; HALT, and LD B,0x22; RET at 0x0200
+        let cpu = run(&[
+            (
+                0x0150,
+                &[0x31, 0x00, 0xD0, 0xCD, 0x00, 0x02, 0x0E, 0x11, 0x76],
+            ),
+            (0x0200, &[0x06, 0x22, 0xC9]),
+        ]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0x22);
+        assert_eq!(cpu.register_file().read_u8(Register::C), 0x11);
+        assert_eq!(cpu.register_file().read_u16(Register::SP), 0xD000);
+        assert_eq!(cpu.mmu().read(0xCFFF), 0x01);
+        assert_eq!(cpu.mmu().read(0xCFFE), 0x56);
+    }
+
+    #[test]
+    fn conditional_call_and_jump_follow_flags() {
+        // LD SP,0xD000; CALL NZ,0x0200; CALL Z,0x0210; JP Z,0x0220, with Z set by the boot ROM
+        let cpu = run(&[
+            (
+                0x0150,
+                &[
+                    0x31, 0x00, 0xD0, 0xC4, 0x00, 0x02, 0xCC, 0x10, 0x02, 0xCA, 0x20, 0x02,
+                ],
+            ),
+            // LD B,0x01; RET
+            (0x0200, &[0x06, 0x01, 0xC9]),
+            // LD C,0x02; RET
+            (0x0210, &[0x0E, 0x02, 0xC9]),
+            // LD D,0x03; HALT
+            (0x0220, &[0x16, 0x03, 0x76]),
+        ]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0x00);
+        assert_eq!(cpu.register_file().read_u8(Register::C), 0x02);
+        assert_eq!(cpu.register_file().read_u8(Register::D), 0x03);
+        assert_eq!(cpu.register_file().read_u16(Register::SP), 0xD000);
+    }
+
+    // Dispatches a timer interrupt with SP at the given address, so the pushed PC of 0x015B can land in IE.
+    // The timer handler sets B to 0x50, while a cancelled dispatch jumps to 0x0000, which sets B to 0xEE
+    fn dispatch_with_sp(sp: u16) -> CPU {
+        let [low, high] = sp.to_le_bytes();
+        run(&[
+            (0x0000, &[0x06, 0xEE, 0x76]),
+            (0x0050, &[0x06, 0x50, 0x76]),
+            // LD SP,sp; LD A,0x04; LDH (IE),A; LDH (IF),A; EI; NOP; HALT
+            (
+                0x0150,
+                &[
+                    0x31, low, high, 0x3E, 0x04, 0xE0, 0xFF, 0xE0, 0x0F, 0xFB, 0x00, 0x76,
+                ],
+            ),
+        ])
+    }
+
+    #[test]
+    fn interrupt_push_of_upper_pc_byte_into_ie_cancels_dispatch() {
+        // The upper byte 0x01 only enables VBlank, which is not pending
+        let cpu = dispatch_with_sp(0x0000);
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0xEE);
+        assert_eq!(cpu.mmu().read(0xFFFF), 0x01);
+    }
+
+    #[test]
+    fn interrupt_push_of_lower_pc_byte_into_ie_comes_too_late() {
+        // The lower byte 0x5B disables the timer interrupt as well, but only after it was resolved
+        let cpu = dispatch_with_sp(0x0001);
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0x50);
+        assert_eq!(cpu.mmu().read(0xFFFF), 0x5B);
+    }
+
+    #[test]
+    fn register_pair_3_selects_sp() {
+        // LD SP,0xD000; INC SP; DEC SP; DEC SP; LD HL,0x0001; ADD HL,SP; LD (0xC000),SP; HALT
+        let cpu = run(&[(
+            0x0150,
+            &[
+                0x31, 0x00, 0xD0, 0x33, 0x3B, 0x3B, 0x21, 0x01, 0x00, 0x39, 0x08, 0x00, 0xC0, 0x76,
+            ],
+        )]);
+
+        assert_eq!(cpu.register_file().read_u16(Register::SP), 0xCFFF);
+        assert_eq!(cpu.register_file().read_u16(Register::HL), 0xD000);
+        assert_eq!(cpu.mmu().read(0xC000), 0xFF);
+        assert_eq!(cpu.mmu().read(0xC001), 0xCF);
+    }
+
+    #[test]
+    fn push_and_pop_select_af() {
+        // LD SP,0xD000; LD BC,0x12FF; PUSH BC; POP AF; PUSH AF; POP DE; HALT
+        let cpu = run(&[(
+            0x0150,
+            &[
+                0x31, 0x00, 0xD0, 0x01, 0xFF, 0x12, 0xC5, 0xF1, 0xF5, 0xD1, 0x76,
+            ],
+        )]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::A), 0x12);
+        assert_eq!(cpu.register_file().read_u8(Register::F), 0xF0);
+        assert_eq!(cpu.register_file().read_u16(Register::DE), 0x12F0);
+        assert_eq!(cpu.register_file().read_u16(Register::SP), 0xD000);
+    }
+
+    #[test]
+    fn immediate_operand_is_consumed() {
+        // AND 0x0F; LD B,0x33; HALT, with A set to 0x01 by the boot ROM
+        let cpu = run(&[(0x0150, &[0xE6, 0x0F, 0x06, 0x33, 0x76])]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::A), 0x01);
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0x33);
+    }
+
+    #[test]
+    fn a_register_operands() {
+        // LD A,0x3C; LD B,A; INC A; LD HL,0xC000; LD (HL),A; LD C,(HL); XOR A; LD D,A; HALT
+        let cpu = run(&[(
+            0x0150,
+            &[
+                0x3E, 0x3C, 0x47, 0x3C, 0x21, 0x00, 0xC0, 0x77, 0x4E, 0xAF, 0x57, 0x76,
+            ],
+        )]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::A), 0x00);
+        assert_eq!(cpu.register_file().read_u8(Register::F), 0x80);
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0x3C);
+        assert_eq!(cpu.register_file().read_u8(Register::C), 0x3D);
+        assert_eq!(cpu.register_file().read_u8(Register::D), 0x00);
+        assert_eq!(cpu.mmu().read(0xC000), 0x3D);
+    }
+
+    #[test]
+    fn cb_rotates_shifts_and_bits() {
+        // LD A,0x81; RLC A; LD B,0x81; RR B; LD C,0x80; SRA C; LD D,0x12; SWAP D; LD E,0x03; SRL E;
+        // SET 7,E; RES 0,E; HALT
+        let cpu = run(&[(
+            0x0150,
+            &[
+                0x3E, 0x81, 0xCB, 0x07, 0x06, 0x81, 0xCB, 0x18, 0x0E, 0x80, 0xCB, 0x29, 0x16, 0x12,
+                0xCB, 0x32, 0x1E, 0x03, 0xCB, 0x3B, 0xCB, 0xFB, 0xCB, 0x83, 0x76,
+            ],
+        )]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::A), 0x03);
+        // RR shifts in the carry RLC shifted out
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0xC0);
+        assert_eq!(cpu.register_file().read_u8(Register::C), 0xC0);
+        assert_eq!(cpu.register_file().read_u8(Register::D), 0x21);
+        assert_eq!(cpu.register_file().read_u8(Register::E), 0x80);
+        // SET and RES keep the carry SRL shifted out
+        assert_eq!(cpu.register_file().read_u8(Register::F), 0x10);
+    }
+
+    #[test]
+    fn arithmetic_results_and_flags() {
+        // LD SP,0xD000; LD A,0x19; ADD A,0x28; DAA; SCF; CP 0x47; PUSH AF; POP DE; LD HL,0x00FF;
+        // LD BC,0x0001; ADD HL,BC; ADD SP,-1; LD B,0x10; DEC B; HALT
+        let cpu = run(&[(
+            0x0150,
+            &[
+                0x31, 0x00, 0xD0, 0x3E, 0x19, 0xC6, 0x28, 0x27, 0x37, 0xFE, 0x47, 0xF5, 0xD1, 0x21,
+                0xFF, 0x00, 0x01, 0x01, 0x00, 0x09, 0xE8, 0xFF, 0x06, 0x10, 0x05, 0x76,
+            ],
+        )]);
+
+        assert_eq!(cpu.register_file().read_u8(Register::A), 0x47);
+        // CP ignores the carry set by SCF
+        assert_eq!(cpu.register_file().read_u8(Register::E), 0xC0);
+        assert_eq!(cpu.register_file().read_u16(Register::HL), 0x0100);
+        assert_eq!(cpu.register_file().read_u16(Register::SP), 0xCFFF);
+        assert_eq!(cpu.register_file().read_u8(Register::B), 0x0F);
+        assert_eq!(cpu.register_file().read_u8(Register::F), 0x60);
+    }
+}
@@ -1,3 +1,4 @@
+// Z, N, H and C are the upper 4 bits of F, the lower 4 bits always read as 0
 pub struct Flags {
     data: u8,
 }
@@ -16,32 +17,32 @@ impl Flags {
     }
 
     pub fn get_z(&self) -> bool {
-        self.get(0)
+        self.get(7)
     }
     pub fn get_n(&self) -> bool {
-        self.get(1)
+        self.get(6)
     }
     pub fn get_h(&self) -> bool {
-        self.get(2)
+        self.get(5)
     }
     pub fn get_c(&self) -> bool {
-        self.get(3)
+        self.get(4)
     }
 
     fn set(&mut self, idx: usize, v: bool) {
-        self.data = (self.data & !(0x1 >> idx)) | (((v as u8) & 0x1) << idx);
+        self.data = (self.data & !(0x1 << idx)) | (((v as u8) & 0x1) << idx);
     }
 
     pub fn set_z(&mut self, v: bool) {
-        self.set(0, v)
+        self.set(7, v)
     }
     pub fn set_n(&mut self, v: bool) {
-        self.set(1, v)
+        self.set(6, v)
     }
     pub fn set_h(&mut self, v: bool) {
-        self.set(2, v)
+        self.set(5, v)
     }
     pub fn set_c(&mut self, v: bool) {
-        self.set(3, v)
+        self.set(4, v)
     }
 }
@@ -14,6 +14,9 @@ use std::rc::Rc;
 pub struct IDU {
     address_bus: Rc<RefCell<Bus<u16>>>,
     register_file: Rc<RefCell<RegisterFile>>,
+
+    // The address incremented or decremented in the current M-cycle, which the memory sees on the bus
+    active_address: Option<u16>,
 }
 
 impl IDU {
@@ -24,9 +27,15 @@ impl IDU {
         IDU {
             address_bus,
             register_file,
+            active_address: None,
         }
     }
 
+    // Returns the address incremented or decremented since the last call, if any
+    pub fn take_active_address(&mut self) -> Option<u16> {
+        self.active_address.take()
+    }
+
     pub fn write_into(&self, register: Register) {
         let address = self.address_bus.borrow().read().unwrap_or_else(|| {
             println!("WARNING: The address bus should not be empty at this point!");
@@ -35,24 +44,26 @@ impl IDU {
         self.register_file.borrow_mut().write_u16(register, address)
     }
 
-    pub fn increment_into(&self, register: Register) {
+    pub fn increment_into(&mut self, register: Register) {
         let address = self.address_bus.borrow().read().unwrap_or_else(|| {
             println!("WARNING: The address bus should not be empty at this point!");
             0
         });
+        self.active_address = Some(address);
         self.register_file
             .borrow_mut()
-            .write_u16(register, address + 1)
+            .write_u16(register, address.wrapping_add(1))
     }
 
-    pub fn decrement_into(&self, register: Register) {
+    pub fn decrement_into(&mut self, register: Register) {
         let address = self.address_bus.borrow().read().unwrap_or_else(|| {
             println!("WARNING: The address bus should not be empty at this point!");
             0
         });
+        self.active_address = Some(address);
         self.register_file
             .borrow_mut()
-            .write_u16(register, address - 1)
+            .write_u16(register, address.wrapping_sub(1))
     }
 
     pub fn adjust_u8_into(&self, register: Register, adjustment: i32) {
@@ -557,11 +557,13 @@ pub enum Instruction {
     RST(u8),
 
     // HALT
-    // Opcode 0b01110110, 1 byte
+    // Stops fetching and executing instructions until an enabled interrupt is requested
+    // Opcode 0b01110110, 1 byte, 1 cycle
     HALT(),
 
     // STOP
-    // Opcode 0b00010000, 1 byte
+    // Enters the low power STOP mode until a button is pressed, or switches the speed of the CGB
+    // Opcode 0b00010000, 2 bytes, 2 cycles
     STOP(),
 
     // DI
@@ -574,6 +576,12 @@ pub enum Instruction {
     // Opcode 0b11111011, 1 byte, 1 cycle
     EI(),
 
+    // Interrupt Service Routine (ISR)
+    // Not an instruction, but dispatches an interrupt instead of the fetched instruction. Pushes PC onto the
+    // stack and jumps to the vector of the interrupt with the highest priority
+    // 5 cycles
+    ISR(),
+
     // NOP
     // No operation. Can be used to add a delay of one machine cycle.
     // Opcode 0b00000000, 1 byte, 1 cycle
@@ -57,11 +57,14 @@ pub enum Register {
     HL,
     // u16
     WZ,
+    // u16
+    AF,
 }
 
-const REGISTER_COUNT: usize = 18;
+const REGISTER_COUNT: usize = 19;
 const DATA_REGISTER_COUNT: usize = 6;
-const REGISTER_PAIR_COUNT: usize = 4;
+const REGISTER_PAIR_COUNT: usize = 5;
+const OPERAND_PAIR_COUNT: usize = 4;
 const REGISTER_FILE_BYTES: usize = 16;
 
 const DATA_REGISTERS: [Register; DATA_REGISTER_COUNT] = [
@@ -78,17 +81,35 @@ const REGISTER_PAIRS: [(Register, Register, Register); REGISTER_PAIR_COUNT] = [
     (Register::D, Register::E, Register::DE),
     (Register::H, Register::L, Register::HL),
     (Register::W, Register::Z, Register::WZ),
+    (Register::A, Register::F, Register::AF),
 ];
 
+// The 16-bit registers selected by bits 4-5 of an opcode
+const OPERAND_PAIRS: [Register; OPERAND_PAIR_COUNT] =
+    [Register::BC, Register::DE, Register::HL, Register::SP];
+
+// PUSH and POP select AF instead of SP
+const STACK_PAIRS: [Register; OPERAND_PAIR_COUNT] =
+    [Register::BC, Register::DE, Register::HL, Register::AF];
+
 impl Register {
+    // The 8-bit register selected by 3 bits of an opcode, where 6 selects (HL) instead and 7 selects A
     pub fn data_register(index: u8) -> Register {
+        if index == 7 {
+            return Register::A;
+        }
         assert!((index as usize) < DATA_REGISTER_COUNT);
         DATA_REGISTERS[index as usize]
     }
 
     pub fn register_pair(index: u8) -> Register {
-        assert!((index as usize) < REGISTER_PAIR_COUNT);
-        REGISTER_PAIRS[index as usize].2
+        assert!((index as usize) < OPERAND_PAIR_COUNT);
+        OPERAND_PAIRS[index as usize]
+    }
+
+    pub fn stack_register_pair(index: u8) -> Register {
+        assert!((index as usize) < OPERAND_PAIR_COUNT);
+        STACK_PAIRS[index as usize]
     }
 
     pub fn index(&self) -> usize {
@@ -0,0 +1,413 @@
+/*!
+The emulator ties the CPU, with the memory and peripherals it drives, to the files on the host. It keeps
+the save file of a battery-backed cartridge up to date by writing it at regular checkpoints while the
+game runs and once more when the emulator shuts down. While recording, it regularly moves the samples of
+the APU into a WAV file.
+https://gbdev.io/pandocs/MBCs.html#battery
+*/
+
+use crate::apu::Recorder;
+use crate::cartridge::{Cartridge, SaveFile};
+use crate::cpu::CPU;
+use crate::joypad::Buttons;
+use crate::model::Model;
+use crate::ppu::{RendererKind, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::serial::{LinkCableEnd, SerialDevice};
+use std::path::Path;
+
+// Roughly every 5 seconds of emulated time
+const CYCLES_PER_CHECKPOINT: u32 = 5 << 20;
+// Roughly every 1/64 seconds of emulated time
+const CYCLES_PER_AUDIO_FLUSH: u32 = 1 << 14;
+// The sample rate of recordings, unless the frontend already chose one
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+pub struct Emulator {
+    cpu: CPU,
+    save_file: Option<SaveFile>,
+    cycles_since_checkpoint: u32,
+
+    recorder: Option<Recorder>,
+    // Whether the frontend collects samples with take_samples
+    playback: bool,
+    // The samples produced since the last call to take_samples
+    samples: Vec<f32>,
+    cycles_since_audio_flush: u32,
+}
+
+impl Emulator {
+    // The save file is only used if the cartridge has a battery
+    pub fn new(cartridge: Cartridge, save_file: Option<SaveFile>, model: Model) -> Emulator {
+        let save_file = save_file.filter(|_| cartridge.has_battery());
+
+        let mut cpu = CPU::new(model);
+        cpu.mmu_mut().insert_cartridge(cartridge);
+        cpu.skip_boot_rom();
+
+        Emulator {
+            cpu,
+            save_file,
+            cycles_since_checkpoint: 0,
+            recorder: None,
+            playback: false,
+            samples: Vec::new(),
+            cycles_since_audio_flush: 0,
+        }
+    }
+
+    pub fn clock_cycle(&mut self) {
+        self.cpu.clock_cycle();
+
+        self.cycles_since_checkpoint += 1;
+        if self.cycles_since_checkpoint == CYCLES_PER_CHECKPOINT {
+            self.cycles_since_checkpoint = 0;
+            self.checkpoint();
+        }
+
+        if self.recorder.is_some() {
+            self.cycles_since_audio_flush += 1;
+            if self.cycles_since_audio_flush == CYCLES_PER_AUDIO_FLUSH {
+                self.cycles_since_audio_flush = 0;
+                self.record_audio();
+            }
+        }
+    }
+
+    // Plugs a device into the link port, such as a SerialCapture collecting the output of test ROMs
+    pub fn connect_serial_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.cpu.mmu_mut().serial_mut().connect(device);
+    }
+
+    // Connects the link ports of both emulators with a cable. Both have to be run in lockstep, one call to
+    // clock_cycle each in turn
+    pub fn connect_link_cable(&mut self, other: &mut Emulator) {
+        let (first, second) = LinkCableEnd::pair();
+        self.connect_serial_device(Box::new(first));
+        other.connect_serial_device(Box::new(second));
+    }
+
+    pub fn set_renderer(&mut self, kind: RendererKind) {
+        self.cpu.mmu_mut().ppu_mut().set_renderer(kind);
+    }
+
+    // Records the output of the APU to a 16-bit PCM WAV file at the path, and the output of every channel
+    // to a file next to it if stems is set. A running recording is stopped first
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P, stems: bool) -> std::io::Result<()> {
+        self.stop_recording()?;
+
+        let sample_rate = self
+            .cpu
+            .mmu()
+            .apu()
+            .sample_rate()
+            .unwrap_or(DEFAULT_SAMPLE_RATE);
+        let recorder = Recorder::create(path, sample_rate, stems)?;
+
+        // Samples produced before the recording started still belong to the frontend
+        self.flush_audio()?;
+        let apu = self.cpu.mmu_mut().apu_mut();
+        if !self.playback {
+            apu.set_sample_rate(Some(sample_rate));
+        }
+        apu.set_stems_enabled(stems);
+        self.recorder = Some(recorder);
+        self.cycles_since_audio_flush = 0;
+        Ok(())
+    }
+
+    // Writes the remaining samples and closes the recording
+    pub fn stop_recording(&mut self) -> std::io::Result<()> {
+        let result = self.flush_audio();
+        self.end_recording();
+        result
+    }
+
+    fn end_recording(&mut self) {
+        if self.recorder.take().is_some() {
+            let apu = self.cpu.mmu_mut().apu_mut();
+            apu.set_stems_enabled(false);
+            if !self.playback {
+                apu.set_sample_rate(None);
+            }
+        }
+    }
+
+    // Moves the samples of the APU into the recording and keeps them for take_samples if the frontend
+    // collects them
+    fn flush_audio(&mut self) -> std::io::Result<()> {
+        let apu = self.cpu.mmu_mut().apu_mut();
+        let samples = apu.take_samples();
+        let stem_samples = apu.take_stem_samples();
+
+        if self.playback {
+            self.samples.extend_from_slice(&samples);
+        }
+        match self.recorder.as_mut() {
+            Some(recorder) => recorder.write(&samples, stem_samples.as_ref()),
+            None => Ok(()),
+        }
+    }
+
+    // Like flush_audio, but stops a recording that cannot be written
+    fn record_audio(&mut self) {
+        if let Err(error) = self.flush_audio()
+            && let Some(recorder) = self.recorder.as_ref()
+        {
+            println!(
+                "WARNING: Could not write the recording {}: {error}",
+                recorder.path().display()
+            );
+            self.end_recording();
+        }
+    }
+
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.cpu.mmu_mut().ppu_mut().set_sprite_limit(enabled);
+    }
+
+    pub fn set_access_blocking(&mut self, enabled: bool) {
+        self.cpu.mmu_mut().ppu_mut().set_access_blocking(enabled);
+    }
+
+    // Writes the save file if the game changed the cartridge RAM since the last checkpoint
+    fn checkpoint(&mut self) {
+        let modified = self
+            .cpu
+            .mmu_mut()
+            .cartridge_mut()
+            .is_some_and(|cartridge| cartridge.take_ram_modified());
+        if modified {
+            self.save();
+        }
+    }
+
+    pub fn save(&mut self) {
+        let (Some(save_file), Some(cartridge)) =
+            (self.save_file.as_ref(), self.cpu.mmu_mut().cartridge_mut())
+        else {
+            return;
+        };
+
+        if let Err(error) = save_file.write(cartridge) {
+            println!(
+                "WARNING: Could not write the save file {}: {error}",
+                save_file.path().display()
+            );
+        }
+    }
+}
+
+// What a frontend with a window needs to show the frames, play the sound and pass on the buttons. The
+// command line frontend runs games without a window, so it uses none of it yet
+#[allow(dead_code)]
+impl Emulator {
+    // Sets the buttons that are held down from now on
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.cpu.mmu_mut().set_buttons(buttons);
+    }
+
+    // The shades 0-3 of the last frame drawn by the PPU, row by row
+    pub fn framebuffer(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.cpu.mmu().ppu().framebuffer()
+    }
+
+    // Returns whether the PPU completed a frame since the last call
+    pub fn take_frame_ready(&mut self) -> bool {
+        self.cpu.mmu_mut().ppu_mut().take_frame_ready()
+    }
+
+    // Makes the APU produce stereo samples at the given rate, to be collected with take_samples. A
+    // recording keeps the rate it was started with, so this has no effect on the rate while recording
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        if self.recorder.is_none() {
+            self.cpu
+                .mmu_mut()
+                .apu_mut()
+                .set_sample_rate(Some(sample_rate));
+        }
+        self.playback = true;
+    }
+
+    // Returns the samples produced since the last call, as interleaved left and right values
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.record_audio();
+        std::mem::take(&mut self.samples)
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.save();
+        self.record_audio();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::{SerialCapture, test_result};
+
+    const INTERNAL_CLOCK: u8 = 0x81;
+    const EXTERNAL_CLOCK: u8 = 0x80;
+
+    // An emulator that sends the bytes one after another with the given SC value, and stores every byte
+    // it receives at 0xC000 onwards. It waits for the end of each transfer in HALT, woken by the Serial
+    // interrupt without dispatching it
+    fn emulator(bytes: &[u8], sc: u8) -> Emulator {
+        // LD HL,IE; LD (HL),Serial
+        let mut code = vec![0x21, 0xFF, 0xFF, 0x36, 0x08];
+        for (index, byte) in bytes.iter().enumerate() {
+            // LD HL,IF; LD (HL),0x00
+            code.extend_from_slice(&[0x21, 0x0F, 0xFF, 0x36, 0x00]);
+            // LD HL,SB; LD (HL),byte; LD HL,SC; LD (HL),sc
+            code.extend_from_slice(&[0x21, 0x01, 0xFF, 0x36, *byte, 0x21, 0x02, 0xFF, 0x36, sc]);
+            // HALT; LD A,(SB); LD (0xC000 + index),A
+            code.extend_from_slice(&[0x76, 0xFA, 0x01, 0xFF, 0xEA, index as u8, 0xC0]);
+        }
+        // JR -2
+        code.extend_from_slice(&[0x18, 0xFE]);
+
+        let cartridge = Cartridge::with_code(&[(0x0150, &code)]);
+        Emulator::new(cartridge, None, Model::DMG)
+    }
+
+    fn received(emulator: &Emulator, count: u16) -> Vec<u8> {
+        (0..count)
+            .map(|index| emulator.cpu.mmu().read(0xC000 + index))
+            .collect()
+    }
+
+    #[test]
+    fn link_cable_exchanges_bytes_in_lockstep() {
+        let mut master = emulator(&[0xA1, 0xA2, 0xA3], INTERNAL_CLOCK);
+        let mut slave = emulator(&[0x11, 0x22, 0x33], EXTERNAL_CLOCK);
+        master.connect_link_cable(&mut slave);
+
+        // A transfer with the internal clock takes 1024 M-cycles
+        for _ in 0..4 * 1024 {
+            master.clock_cycle();
+            slave.clock_cycle();
+        }
+
+        assert_eq!(received(&master, 3), [0x11, 0x22, 0x33]);
+        assert_eq!(received(&slave, 3), [0xA1, 0xA2, 0xA3]);
+    }
+
+    #[test]
+    fn link_cable_without_waiting_partner_reads_ff() {
+        // Both sides drive the clock, so neither of them waits for a byte
+        let mut first = emulator(&[0xA1], INTERNAL_CLOCK);
+        let mut second = emulator(&[0x11], INTERNAL_CLOCK);
+        first.connect_link_cable(&mut second);
+
+        for _ in 0..2 * 1024 {
+            first.clock_cycle();
+            second.clock_cycle();
+        }
+
+        assert_eq!(received(&first, 1), [0xFF]);
+        assert_eq!(received(&second, 1), [0xFF]);
+    }
+
+    // Both players run the same program, which finds out who drives the clock like the cable club of
+    // Pokémon does: it listens with the external clock and 0x02 in SB for a while, then tries to send
+    // 0x01 with the internal clock, until it receives one of the two. The one that received 0x02 is the
+    // master, and both then trade the three bytes of their party at 0x0200. The role ends up at 0xC000
+    // as the SC value used, and the party received at 0xC001
+    fn link_player(party: &[u8; 3]) -> Emulator {
+        let code = [
+            // 0x0150: LD HL,IE; LD (HL),Serial
+            0x21, 0xFF, 0xFF, 0x36, 0x08,
+            // 0x0155 retry: XOR A; LDH (IF),A; LD A,0x02; LDH (SB),A; LD A,0x80; LDH (SC),A; LD B,0
+            0xAF, 0xE0, 0x0F, 0x3E, 0x02, 0xE0, 0x01, 0x3E, 0x80, 0xE0, 0x02, 0x06, 0x00,
+            // 0x0162 listen: LDH A,(IF); AND Serial; JR NZ,received; DEC B; JR NZ,listen
+            0xF0, 0x0F, 0xE6, 0x08, 0x20, 0x0F, 0x05, 0x20, 0xF7,
+            // 0x016B: XOR A; LDH (IF),A; LD A,0x01; LDH (SB),A; LD A,0x81; LDH (SC),A; HALT
+            0xAF, 0xE0, 0x0F, 0x3E, 0x01, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x76,
+            // 0x0177 received: LDH A,(SB); LD D,0x80; CP 0x01; JR Z,trade; LD D,0x81; CP 0x02;
+            // JR Z,trade; JR retry
+            0xF0, 0x01, 0x16, 0x80, 0xFE, 0x01, 0x28, 0x08, 0x16, 0x81, 0xFE, 0x02, 0x28, 0x02,
+            0x18, 0xCE,
+            // 0x0187 trade: LD A,D; LD (0xC000),A; LD HL,0x0200; LD BC,0xC001; LD E,3
+            0x7A, 0xEA, 0x00, 0xC0, 0x21, 0x00, 0x02, 0x01, 0x01, 0xC0, 0x1E, 0x03,
+            // 0x0193 next: XOR A; LDH (IF),A; LD A,(HL+); LDH (SB),A; LD A,D; LDH (SC),A; HALT;
+            // LDH A,(SB); LD (BC),A; INC BC; DEC E; JR NZ,next
+            0xAF, 0xE0, 0x0F, 0x2A, 0xE0, 0x01, 0x7A, 0xE0, 0x02, 0x76, 0xF0, 0x01, 0x02, 0x03,
+            0x1D, 0x20, 0xEF, // 0x01A4 done: JR done
+            0x18, 0xFE,
+        ];
+        let cartridge = Cartridge::with_code(&[(0x0150, &code), (0x0200, party)]);
+        Emulator::new(cartridge, None, Model::DMG)
+    }
+
+    #[test]
+    fn link_handshake_picks_roles_and_trades() {
+        let mut first = link_player(&[0x99, 0x15, 0xB0]);
+        let mut second = link_player(&[0x54, 0xA5, 0x24]);
+        first.connect_link_cable(&mut second);
+
+        // The second player enters the cable club later. Like on hardware, two players that alternate in
+        // step would both keep sending 0x01 at the same time and never agree on the master
+        for _ in 0..3000 {
+            first.clock_cycle();
+        }
+        for _ in 0..20 * 1024 {
+            first.clock_cycle();
+            second.clock_cycle();
+        }
+
+        let roles = [received(&first, 1)[0], received(&second, 1)[0]];
+        assert!(
+            roles == [INTERNAL_CLOCK, EXTERNAL_CLOCK] || roles == [EXTERNAL_CLOCK, INTERNAL_CLOCK]
+        );
+        assert_eq!(received(&first, 4)[1..], [0x54, 0xA5, 0x24]);
+        assert_eq!(received(&second, 4)[1..], [0x99, 0x15, 0xB0]);
+    }
+
+    // A test ROM in the style of Blargg's: it checks that 0x19 + 0x28 adjusts to the given BCD value, and
+    // prints Passed or Failed over the serial port, one byte at a time with the internal clock
+    fn test_rom(expected: u8) -> Cartridge {
+        let code = [
+            // 0x0150: LD A,0x19; ADD A,0x28; DAA; LD HL,passed; CP expected; JR Z,print
+            0x3E, 0x19, 0xC6, 0x28, 0x27, 0x21, 0x70, 0x01, 0xFE, expected, 0x28, 0x03,
+            // 0x015C: LD HL,failed
+            0x21, 0x78, 0x01,
+            // 0x015F print: LD A,(HL+); OR A; JR Z,$; LDH (SB),A; LD A,0x81; LDH (SC),A
+            0x2A, 0xB7, 0x28, 0xFE, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02,
+            // 0x0169 wait: LDH A,(SC); ADD A,A; JR C,wait; JR print
+            0xF0, 0x02, 0x87, 0x38, 0xFB, 0x18, 0xEF,
+        ];
+        Cartridge::with_code(&[
+            (0x0150, &code),
+            (0x0170, b"Passed\n\0"),
+            (0x0178, b"Failed\n\0"),
+        ])
+    }
+
+    // Runs the test ROM until it reports a result, like --test-rom does
+    fn run_test_rom(expected: u8) -> (Option<bool>, String) {
+        let mut emulator = Emulator::new(test_rom(expected), None, Model::DMG);
+        let capture = SerialCapture::new();
+        let output = capture.output();
+        emulator.connect_serial_device(Box::new(capture));
+
+        // Every byte takes 1024 M-cycles
+        for _ in 0..8 * 1024 + 1000 {
+            emulator.clock_cycle();
+            if let Some(passed) = test_result(&output.borrow()) {
+                return (Some(passed), output.borrow().clone());
+            }
+        }
+        (None, output.borrow().clone())
+    }
+
+    #[test]
+    fn test_rom_reports_passed() {
+        assert_eq!(run_test_rom(0x47), (Some(true), String::from("Passed")));
+    }
+
+    #[test]
+    fn test_rom_reports_failed() {
+        assert_eq!(run_test_rom(0x41), (Some(false), String::from("Failed")));
+    }
+}
@@ -0,0 +1,74 @@
+/*!
+Peripherals request interrupts by setting their bit in the Interrupt Flag (IF) register at 0xFF0F. The
+CPU services a requested interrupt if it is also enabled in the Interrupt Enable (IE) register and the
+Interrupt Master Enable (IME) flag is set, with lower bits having a higher priority.
+https://gbdev.io/pandocs/Interrupts.html
+*/
+
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, EnumIter)]
+pub enum Interrupt {
+    // Bit 0, vector 0x0040
+    VBlank,
+    // Bit 1, vector 0x0048
+    STAT,
+    // Bit 2, vector 0x0050
+    Timer,
+    // Bit 3, vector 0x0058
+    Serial,
+    // Bit 4, vector 0x0060
+    Joypad,
+}
+
+// Only the lower 5 bits of IF and IE belong to an interrupt
+pub const INTERRUPT_MASK: u8 = 0x1F;
+
+impl Interrupt {
+    pub fn bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+
+    pub fn vector(&self) -> u16 {
+        0x0040 + 8 * (*self as u16)
+    }
+
+    // The interrupt with the highest priority among the set bits
+    pub fn highest_priority(bits: u8) -> Option<Interrupt> {
+        Interrupt::iter().find(|interrupt| (bits & interrupt.bit()) != 0)
+    }
+}
+
+pub struct InterruptFlags {
+    value: u8,
+}
+
+impl InterruptFlags {
+    pub fn new() -> InterruptFlags {
+        InterruptFlags {
+            value: !INTERRUPT_MASK,
+        }
+    }
+
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.value |= interrupt.bit();
+    }
+
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.value &= !interrupt.bit();
+    }
+
+    pub fn pending(&self) -> u8 {
+        self.value & INTERRUPT_MASK
+    }
+
+    // The upper 3 bits are not connected and always read as 1
+    pub fn read(&self) -> u8 {
+        self.value | !INTERRUPT_MASK
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.value = value | !INTERRUPT_MASK;
+    }
+}
@@ -0,0 +1,106 @@
+/*!
+The eight buttons are wired as a 2x4 matrix. Writing 0 to bit 4 of P1 (0xFF00) selects the directions and
+writing 0 to bit 5 selects the action buttons. A pressed button of a selected group pulls its input line
+P10-P13 in the lower 4 bits low, so with both groups selected, a line reads as 0 if either of its buttons
+is pressed. The upper 2 bits are unused and read as 1. Any input line falling from 1 to 0 requests the
+Joypad interrupt, and a low input line also ends STOP mode.
+https://gbdev.io/pandocs/Joypad_Input.html
+*/
+
+use crate::interrupts::{Interrupt, InterruptFlags};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const SELECT_DIRECTIONS: u8 = 0x10;
+const SELECT_BUTTONS: u8 = 0x20;
+const SELECT_MASK: u8 = SELECT_DIRECTIONS | SELECT_BUTTONS;
+const LINES_MASK: u8 = 0x0F;
+const UNUSED_BITS: u8 = 0xC0;
+
+// The buttons that are currently held down
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Buttons {
+    pub right: bool,
+    pub left: bool,
+    pub up: bool,
+    pub down: bool,
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+}
+
+impl Buttons {
+    // The bits of the input lines P10-P13 pulled low by the pressed directions
+    fn directions(&self) -> u8 {
+        (self.right as u8)
+            | ((self.left as u8) << 1)
+            | ((self.up as u8) << 2)
+            | ((self.down as u8) << 3)
+    }
+
+    // The bits of the input lines P10-P13 pulled low by the pressed action buttons
+    fn actions(&self) -> u8 {
+        (self.a as u8)
+            | ((self.b as u8) << 1)
+            | ((self.select as u8) << 2)
+            | ((self.start as u8) << 3)
+    }
+}
+
+pub struct Joypad {
+    interrupt_flags: Rc<RefCell<InterruptFlags>>,
+
+    // Bits 4 and 5 of P1, a group of buttons is selected while its bit is 0
+    select: u8,
+    buttons: Buttons,
+    // The state of the input lines after the last change, to detect falling lines
+    lines: u8,
+}
+
+impl Joypad {
+    pub fn new(interrupt_flags: Rc<RefCell<InterruptFlags>>) -> Joypad {
+        Joypad {
+            interrupt_flags,
+            select: 0,
+            buttons: Buttons::default(),
+            lines: LINES_MASK,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        UNUSED_BITS | self.select | self.lines
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.select = value & SELECT_MASK;
+        self.update_lines();
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+        self.update_lines();
+    }
+
+    // Whether any of the input lines is pulled low by a pressed button of a selected group
+    pub fn line_low(&self) -> bool {
+        self.lines != LINES_MASK
+    }
+
+    // Pressing a button and selecting a group with a pressed button both pull a line low
+    fn update_lines(&mut self) {
+        let mut pressed = 0;
+        if (self.select & SELECT_DIRECTIONS) == 0 {
+            pressed |= self.buttons.directions();
+        }
+        if (self.select & SELECT_BUTTONS) == 0 {
+            pressed |= self.buttons.actions();
+        }
+        let lines = LINES_MASK & !pressed;
+
+        if (self.lines & !lines) != 0 {
+            self.interrupt_flags.borrow_mut().request(Interrupt::Joypad);
+        }
+        self.lines = lines;
+    }
+}
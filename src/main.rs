@@ -1,37 +1,241 @@
-use crate::cpu::CPU;
+use crate::cartridge::{Cartridge, CgbSupport, SaveFile};
+use crate::emulator::Emulator;
+use crate::model::Model;
+use crate::ppu::RendererKind;
+#[cfg(unix)]
+use crate::serial::SocketLink;
+use crate::serial::{SerialCapture, test_result};
 
+mod apu;
 mod bus;
+mod cartridge;
 mod cpu;
+mod emulator;
+mod interrupts;
+mod joypad;
+mod memory;
+mod model;
+mod ppu;
+mod serial;
+mod shutdown;
+mod timer;
+
+const USAGE: &str = "Usage: GameboyEmulator [options] <rom>
+
+Options:
+  --cgb                          Run games that support the Game Boy Color on a CGB, without its colors and
+                                 banked memory, which are not emulated yet
+  --sync-rtc                     Advance the cartridge clock by the time that passed since the save file was written
+  --renderer <fifo|scanline>     Draw with the accurate pixel FIFO (default) or the faster scanline renderer
+  --no-sprite-limit              Draw all objects on a line instead of at most 10, which removes flickering
+  --no-access-blocking           Let the CPU access VRAM and OAM while the PPU uses them, for debugging
+  --record <file.wav>            Record the sound output to a 16-bit PCM WAV file
+  --record-stems                 With --record, record every sound channel to a file next to it as well,
+                                 such as file.square1.wav, file.square2.wav, file.wave.wav and file.noise.wav
+  --test-rom                     Print what a test ROM sends over the serial port and exit once it reports
+                                 Passed or Failed, with status 0 or 1
+  --link-listen <socket>         Wait for another emulator to connect its link cable to the Unix socket,
+                                 only on Unix
+  --link-connect <socket>        Connect the link cable to another emulator listening on the Unix socket,
+                                 only on Unix
+  --link-rom <rom>               Run a second game next to the first one, connected with a link cable";
 
 fn main() {
-    let mut cpu = CPU::new();
-
-    // Test LD HL, SP+e
-    // LD HL, 0b0000000000110011
-    cpu.data_bus_mut().write(0b00100001);
-    cpu.clock_cycle();
-    cpu.data_bus_mut().write(0b00110101);
-    cpu.clock_cycle();
-    cpu.data_bus_mut().write(0b00000000);
-    cpu.clock_cycle();
-    cpu.data_bus_mut().write(0b00000000);
-    cpu.clock_cycle();
-
-    // LD SP, HL
-    cpu.data_bus_mut().write(0b11111001);
-    cpu.clock_cycle();
-    cpu.data_bus_mut().write(0b00000000);
-    cpu.clock_cycle();
-
-    // LD HL, SP+e
-    cpu.data_bus_mut().write(0b11111000);
-    cpu.clock_cycle();
-    cpu.data_bus_mut().write(0xCC);
-    cpu.clock_cycle();
-    cpu.data_bus_mut().write(0x00);
-    cpu.clock_cycle();
-    cpu.data_bus_mut().write(0x00);
-    cpu.clock_cycle();
-
-    println!("{}", cpu.register_file());
+    let mut rom_path = None;
+    let mut cgb = false;
+    let mut sync_rtc = false;
+    let mut renderer = RendererKind::Fifo;
+    let mut sprite_limit = true;
+    let mut access_blocking = true;
+    let mut record_path = None;
+    let mut record_stems = false;
+    let mut test_rom = false;
+    // Unix sockets connect the link cable of two emulators, which other systems have no flags for
+    #[cfg(unix)]
+    let mut link = None;
+    #[cfg(not(unix))]
+    let link: Option<(bool, String)> = None;
+    let mut link_rom = None;
+    let mut arguments = std::env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--cgb" => cgb = true,
+            "--sync-rtc" => sync_rtc = true,
+            "--no-sprite-limit" => sprite_limit = false,
+            "--no-access-blocking" => access_blocking = false,
+            "--record-stems" => record_stems = true,
+            "--test-rom" => test_rom = true,
+            #[cfg(unix)]
+            "--link-listen" | "--link-connect" => match arguments.next() {
+                Some(socket) => link = Some((argument == "--link-listen", socket)),
+                None => {
+                    eprintln!("{USAGE}");
+                    std::process::exit(1);
+                }
+            },
+            "--link-rom" => match arguments.next() {
+                Some(path) => link_rom = Some(path),
+                None => {
+                    eprintln!("{USAGE}");
+                    std::process::exit(1);
+                }
+            },
+            "--record" => match arguments.next() {
+                Some(path) => record_path = Some(path),
+                None => {
+                    eprintln!("{USAGE}");
+                    std::process::exit(1);
+                }
+            },
+            "--renderer" => match arguments.next().as_deref() {
+                Some("fifo") => renderer = RendererKind::Fifo,
+                Some("scanline") => renderer = RendererKind::Scanline,
+                _ => {
+                    eprintln!("{USAGE}");
+                    std::process::exit(1);
+                }
+            },
+            _ if !argument.starts_with("--") && rom_path.is_none() => rom_path = Some(argument),
+            _ => {
+                eprintln!("{USAGE}");
+                std::process::exit(1);
+            }
+        }
+    }
+    let Some(path) = rom_path else {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    };
+    // Test ROMs report their results over the serial port, which the link cable would take, and the serial
+    // port only has room for one cable
+    if (test_rom && (link.is_some() || link_rom.is_some()))
+        || (link.is_some() && link_rom.is_some())
+    {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    }
+
+    let mut emulator = load_emulator(&path, sync_rtc, cgb);
+    emulator.set_renderer(renderer);
+    emulator.set_sprite_limit(sprite_limit);
+    emulator.set_access_blocking(access_blocking);
+    if let Some(record_path) = record_path
+        && let Err(error) = emulator.start_recording(&record_path, record_stems)
+    {
+        eprintln!("Could not record to {record_path}: {error}");
+        std::process::exit(1);
+    }
+    #[cfg(unix)]
+    if let Some((listen, socket)) = link {
+        let connected = if listen {
+            println!("Waiting for the other emulator on {socket}");
+            SocketLink::listen(&socket)
+        } else {
+            SocketLink::connect(&socket)
+        };
+        match connected {
+            Ok(link) => emulator.connect_serial_device(Box::new(link)),
+            Err(error) => {
+                eprintln!("Could not connect the link cable to {socket}: {error}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // The second game runs in lockstep with the first one, so the link cable exchanges the bytes in time
+    let mut partner = link_rom.map(|link_path| {
+        let mut partner = load_emulator(&link_path, sync_rtc, cgb);
+        partner.set_renderer(renderer);
+        partner.set_sprite_limit(sprite_limit);
+        partner.set_access_blocking(access_blocking);
+        emulator.connect_link_cable(&mut partner);
+        partner
+    });
+
+    let serial_output = test_rom.then(|| {
+        let capture = SerialCapture::new();
+        let output = capture.output();
+        emulator.connect_serial_device(Box::new(capture));
+        output
+    });
+
+    // Without the handlers, Ctrl+C still quits, but without writing the save file
+    if let Err(error) = shutdown::install_handlers() {
+        println!("WARNING: Could not handle Ctrl+C: {error}");
+    }
+    let mut serial_length = 0;
+    let status = loop {
+        // A test ROM that is interrupted did not pass
+        if shutdown::requested() {
+            break if test_rom { 1 } else { 0 };
+        }
+
+        emulator.clock_cycle();
+        if let Some(partner) = partner.as_mut() {
+            partner.clock_cycle();
+        }
+
+        if let Some(output) = serial_output.as_ref()
+            && output.borrow().len() != serial_length
+        {
+            let output = output.borrow();
+            serial_length = output.len();
+            if let Some(passed) = test_result(&output) {
+                println!("{output}");
+                break if passed { 0 } else { 1 };
+            }
+        }
+    };
+
+    // Dropping the emulators writes the save files and finishes the recording
+    drop(emulator);
+    drop(partner);
+    std::process::exit(status);
+}
+
+// Loads the game at the path together with its save file, and exits if either of them cannot be loaded
+fn load_emulator(path: &str, sync_rtc: bool, cgb: bool) -> Emulator {
+    let mut cartridge = match Cartridge::from_file(path) {
+        Ok(cartridge) => cartridge,
+        Err(error) => {
+            eprintln!("Could not load {path}: {error}");
+            std::process::exit(1);
+        }
+    };
+    println!("{}", cartridge.header());
+    if !cartridge.header().global_checksum_matches() {
+        println!(
+            "WARNING: The global checksum of the ROM is incorrect, it may be corrupted or patched"
+        );
+    }
+    if !cartridge.header().rom_size_matches() {
+        println!(
+            "WARNING: The ROM is larger than its header declares, it may be overdumped or patched"
+        );
+    }
+    cartridge.set_rumble_callback(Box::new(|motor_on| {
+        println!("Rumble {}", if motor_on { "on" } else { "off" })
+    }));
+
+    let save_file = SaveFile::for_rom(path);
+    if cartridge.has_battery() {
+        match save_file.load(&mut cartridge) {
+            Ok(Some(saved_at)) if sync_rtc => cartridge.sync_rtc_to_wall_clock(saved_at),
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!(
+                    "Could not load the save file {}: {error}",
+                    save_file.path().display()
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let model = Model::for_cartridge(cartridge.header(), cgb);
+    if model == Model::DMG && cartridge.header().cgb_support == CgbSupport::Only {
+        println!("WARNING: The game only runs on a Game Boy Color, try --cgb");
+    }
+
+    Emulator::new(cartridge, Some(save_file), model)
 }
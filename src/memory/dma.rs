@@ -0,0 +1,96 @@
+/*!
+OAM DMA copies 160 bytes from XX00-XX9F to OAM, one byte per M-cycle, after a write of XX to 0xFF46. The
+transfer starts one M-cycle after the write and then takes 160 M-cycles. While it runs, the DMA is a second
+master on the bus it reads from: the CPU reading from that bus sees the byte the DMA is transferring instead,
+its writes to that bus are lost, and OAM is not accessible at all. HRAM and the IO registers sit on neither
+bus, which is why games run the routine waiting for the transfer from HRAM.
+https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+*/
+
+pub const TRANSFER_SIZE: u16 = 0xA0;
+
+// The M-cycles from the write to 0xFF46 until the first byte is transferred
+const START_DELAY: u8 = 2;
+
+// The buses a DMA transfer can read from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryBus {
+    // The cartridge ROM and RAM and WRAM
+    External,
+    VRAM,
+}
+
+impl MemoryBus {
+    // Returns the bus the address is reached through, or None for OAM, the IO registers and HRAM
+    pub fn of(address: u16) -> Option<MemoryBus> {
+        match address {
+            0x8000..=0x9FFF => Some(MemoryBus::VRAM),
+            0x0000..=0xFDFF => Some(MemoryBus::External),
+            _ => None,
+        }
+    }
+}
+
+pub struct DMA {
+    // 0xFF46, the upper byte of the source address
+    register: u8,
+    // The M-cycles until the transfer requested by the last write to 0xFF46 starts
+    start_delay: u8,
+    // The offset of the byte transferred next, while a transfer is running
+    offset: Option<u16>,
+    // The upper byte of the source address of the running transfer
+    source: u8,
+}
+
+impl DMA {
+    pub fn new() -> DMA {
+        DMA {
+            register: 0xFF,
+            start_delay: 0,
+            offset: None,
+            source: 0,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    // Requests a new transfer. A running transfer continues until the new one starts
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+        self.start_delay = START_DELAY;
+    }
+
+    // The address the DMA reads from in this M-cycle, while a transfer is running. Sources above 0xDF00
+    // reach WRAM through its echo
+    pub fn source_address(&self) -> Option<u16> {
+        let source = if self.source >= 0xE0 {
+            self.source - 0x20
+        } else {
+            self.source
+        };
+        self.offset.map(|offset| ((source as u16) << 8) | offset)
+    }
+
+    // The offset inside OAM the DMA writes to in this M-cycle, while a transfer is running
+    pub fn destination_offset(&self) -> Option<usize> {
+        self.offset.map(|offset| offset as usize)
+    }
+
+    // Advances to the next byte after the one of this M-cycle was transferred, and starts a requested
+    // transfer once its delay is over
+    pub fn clock_cycle(&mut self) {
+        if let Some(offset) = self.offset {
+            self.offset = (offset + 1 < TRANSFER_SIZE).then_some(offset + 1);
+        }
+
+        if self.start_delay > 0 {
+            self.start_delay -= 1;
+            if self.start_delay == 0 {
+                self.source = self.register;
+                self.offset = Some(0);
+            }
+        }
+    }
+}
@@ -0,0 +1,590 @@
+/*!
+The Memory Management Unit (MMU) answers the read and write cycles that the CPU core puts on its
+address and data buses. It decodes the 16-bit address space into the cartridge ROM banks, Video RAM
+(VRAM), external RAM, Work RAM (WRAM) and its echo, Object Attribute Memory (OAM), the unusable
+region, the IO registers, High RAM (HRAM) and the Interrupt Enable (IE) register.
+https://gbdev.io/pandocs/Memory_Map.html
+*/
+
+use crate::apu::APU;
+use crate::bus::{Bus, Signal};
+use crate::cartridge::Cartridge;
+use crate::cpu::register_file::{Register, RegisterFile};
+use crate::interrupts::InterruptFlags;
+use crate::joypad::{Buttons, Joypad};
+use crate::memory::dma::{DMA, MemoryBus};
+use crate::model::Model;
+use crate::ppu::{OamCorruption, PPU};
+use crate::serial::Serial;
+use crate::timer::Timer;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const WRAM_SIZE: usize = 0x2000;
+const IO_SIZE: usize = 0x80;
+const HRAM_SIZE: usize = 0x7F;
+
+// Offsets of the IO registers inside the IO region
+const P1: usize = 0x00;
+const SB: usize = 0x01;
+const SC: usize = 0x02;
+const DIV: usize = 0x04;
+const TIMA: usize = 0x05;
+const TMA: usize = 0x06;
+const TAC: usize = 0x07;
+const IF: usize = 0x0F;
+const NR10: usize = 0x10;
+const NR52: usize = 0x26;
+const WAVE_RAM_START: usize = 0x30;
+const WAVE_RAM_END: usize = 0x3F;
+const LCDC: usize = 0x40;
+const DMA: usize = 0x46;
+const WX: usize = 0x4B;
+const KEY1: usize = 0x4D;
+const OPRI: usize = 0x6C;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    // 0x0000-0x3FFF
+    // The first 16 KiB of the cartridge ROM, always mapped
+    RomBank0,
+    // 0x4000-0x7FFF
+    // A switchable 16 KiB cartridge ROM bank
+    RomBankN,
+    // 0x8000-0x9FFF
+    VRAM,
+    // 0xA000-0xBFFF
+    // RAM located on the cartridge, if any
+    ExternalRAM,
+    // 0xC000-0xDFFF
+    WRAM,
+    // 0xE000-0xFDFF
+    // Mirror of 0xC000-0xDDFF
+    EchoRAM,
+    // 0xFE00-0xFE9F
+    OAM,
+    // 0xFEA0-0xFEFF
+    // Not connected to anything, reads return 0x00 and writes are ignored
+    Unusable,
+    // 0xFF00-0xFF7F
+    IO,
+    // 0xFF80-0xFFFE
+    HRAM,
+    // 0xFFFF
+    IE,
+}
+
+impl Region {
+    // Returns the region an address belongs to, together with the offset of the address inside it
+    pub fn decode(address: u16) -> (Region, usize) {
+        let address = address as usize;
+        match address {
+            0x0000..=0x3FFF => (Region::RomBank0, address),
+            0x4000..=0x7FFF => (Region::RomBankN, address - 0x4000),
+            0x8000..=0x9FFF => (Region::VRAM, address - 0x8000),
+            0xA000..=0xBFFF => (Region::ExternalRAM, address - 0xA000),
+            0xC000..=0xDFFF => (Region::WRAM, address - 0xC000),
+            0xE000..=0xFDFF => (Region::EchoRAM, address - 0xE000),
+            0xFE00..=0xFE9F => (Region::OAM, address - 0xFE00),
+            0xFEA0..=0xFEFF => (Region::Unusable, address - 0xFEA0),
+            0xFF00..=0xFF7F => (Region::IO, address - 0xFF00),
+            0xFF80..=0xFFFE => (Region::HRAM, address - 0xFF80),
+            _ => (Region::IE, 0),
+        }
+    }
+}
+
+pub struct MMU {
+    data_bus: Rc<RefCell<Bus<u8>>>,
+    address_bus: Rc<RefCell<Bus<u16>>>,
+    register_file: Rc<RefCell<RegisterFile>>,
+    interrupt_flags: Rc<RefCell<InterruptFlags>>,
+
+    model: Model,
+    // KEY1 bit 0, a speed switch is performed by the next STOP
+    speed_switch_armed: bool,
+    // KEY1 bit 7
+    double_speed: bool,
+    // Hardware running at the normal rate only advances every other cycle in double speed mode
+    odd_cycle: bool,
+    // The CPU read or wrote 0xFE00-0xFEFF, or the IDU incremented or decremented such an address, in the
+    // current M-cycle. On the DMG, this corrupts OAM during mode 2
+    oam_bug_read: bool,
+    oam_bug_write: bool,
+    oam_bug_idu: bool,
+
+    timer: Timer,
+    joypad: Joypad,
+    serial: Serial,
+    apu: APU,
+    ppu: PPU,
+    dma: DMA,
+    cartridge: Option<Cartridge>,
+    wram: [u8; WRAM_SIZE],
+    io: [u8; IO_SIZE],
+    hram: [u8; HRAM_SIZE],
+}
+
+impl MMU {
+    pub fn new(
+        data_bus: Rc<RefCell<Bus<u8>>>,
+        address_bus: Rc<RefCell<Bus<u16>>>,
+        register_file: Rc<RefCell<RegisterFile>>,
+        interrupt_flags: Rc<RefCell<InterruptFlags>>,
+        model: Model,
+    ) -> MMU {
+        MMU {
+            data_bus,
+            address_bus,
+            register_file,
+            interrupt_flags: Rc::clone(&interrupt_flags),
+            model,
+            speed_switch_armed: false,
+            double_speed: false,
+            odd_cycle: false,
+            oam_bug_read: false,
+            oam_bug_write: false,
+            oam_bug_idu: false,
+            timer: Timer::new(Rc::clone(&interrupt_flags)),
+            joypad: Joypad::new(Rc::clone(&interrupt_flags)),
+            serial: Serial::new(Rc::clone(&interrupt_flags), model),
+            apu: APU::new(model),
+            ppu: PPU::new(Rc::clone(&interrupt_flags), model),
+            dma: DMA::new(),
+            cartridge: None,
+            wram: [0; WRAM_SIZE],
+            io: [0; IO_SIZE],
+            hram: [0; HRAM_SIZE],
+        }
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = Some(cartridge);
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn speed_switch_armed(&self) -> bool {
+        self.speed_switch_armed
+    }
+
+    // Toggles between normal and double speed, as done by STOP when a speed switch is armed
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+        self.odd_cycle = false;
+    }
+
+    // Puts the hardware into the state the boot ROM leaves it in
+    pub fn skip_boot_rom(&mut self) {
+        // The DMG boot ROM always takes the same amount of cycles, while the CGB one varies
+        if self.model == Model::DMG {
+            self.timer.set_counter(0xABCC);
+        }
+        self.serial.skip_boot_rom();
+        self.apu.skip_boot_rom();
+    }
+
+    // STOP resets the divider
+    pub fn reset_div(&mut self) {
+        self.write_div();
+    }
+
+    fn write_div(&mut self) {
+        let apu_bit = self.div_apu_bit();
+        let counter = self.timer.counter();
+        self.timer.write_div();
+        self.clock_frame_sequencer(apu_bit);
+        self.serial.clock_counter(counter, self.timer.counter());
+    }
+
+    // The DIV bit whose falling edge clocks the frame sequencer of the APU. It is one bit higher in double
+    // speed mode, so the frame sequencer keeps its rate
+    fn div_apu_bit(&self) -> bool {
+        let bit = if self.double_speed { 13 } else { 12 };
+        (self.timer.counter() >> bit) & 0x01 == 0x01
+    }
+
+    fn clock_frame_sequencer(&mut self, old_apu_bit: bool) {
+        if old_apu_bit && !self.div_apu_bit() {
+            self.apu.clock_frame_sequencer();
+        }
+    }
+
+    pub fn apu(&self) -> &APU {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+
+    // Whether any of the joypad input lines P10-P13 is pulled low by a pressed button
+    pub fn joypad_line_low(&self) -> bool {
+        self.joypad.line_low()
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.joypad.set_buttons(buttons);
+    }
+
+    pub fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    // Advances the hardware connected to the memory by one M-cycle of the CPU. The timer runs at the rate
+    // of the CPU, so it is twice as fast in double speed mode, while the PPU and APU keep the normal rate
+    pub fn clock_cycle(&mut self) {
+        self.trigger_oam_bug();
+        let apu_bit = self.div_apu_bit();
+        let counter = self.timer.counter();
+        self.timer.clock_cycle();
+        self.clock_frame_sequencer(apu_bit);
+        self.serial.clock_counter(counter, self.timer.counter());
+        self.serial.clock_cycle();
+        self.transfer_dma();
+
+        let dots = if self.double_speed { 2 } else { 4 };
+        for _ in 0..dots {
+            self.ppu.tick();
+            self.apu.tick();
+        }
+
+        self.clock_cartridge();
+    }
+
+    // Tells the memory about the address the IDU of the CPU incremented or decremented in this M-cycle
+    pub fn idu_activity(&mut self, address: u16) {
+        if is_oam_bug_address(address) {
+            self.oam_bug_idu = true;
+        }
+    }
+
+    // Corrupts OAM according to the accesses to 0xFE00-0xFEFF in this M-cycle
+    fn trigger_oam_bug(&mut self) {
+        let corruption = match (self.oam_bug_read, self.oam_bug_write, self.oam_bug_idu) {
+            (true, _, true) => Some(OamCorruption::ReadWithIncDec),
+            (true, _, false) => Some(OamCorruption::Read),
+            (false, true, _) | (false, false, true) => Some(OamCorruption::Write),
+            (false, false, false) => None,
+        };
+        self.oam_bug_read = false;
+        self.oam_bug_write = false;
+        self.oam_bug_idu = false;
+
+        if self.model == Model::DMG
+            && let Some(corruption) = corruption
+        {
+            self.ppu.corrupt_oam(corruption);
+        }
+    }
+
+    // Copies the byte of this M-cycle of a running OAM DMA transfer
+    fn transfer_dma(&mut self) {
+        if let Some(source) = self.dma.source_address()
+            && let Some(offset) = self.dma.destination_offset()
+        {
+            let value = self.read(source);
+            self.ppu.write_oam(offset, value);
+        }
+        self.dma.clock_cycle();
+    }
+
+    // Advances the cartridge hardware, which keeps running in STOP mode as it has its own clock
+    pub fn clock_cartridge(&mut self) {
+        self.odd_cycle = !self.odd_cycle;
+        let normal_speed_cycle = !self.double_speed || self.odd_cycle;
+
+        if normal_speed_cycle && let Some(cartridge) = self.cartridge.as_mut() {
+            cartridge.clock_cycle();
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        let (region, offset) = Region::decode(address);
+
+        match region {
+            // Without a cartridge, nothing drives the data bus and it floats high
+            Region::RomBank0 | Region::RomBankN => self
+                .cartridge
+                .as_ref()
+                .map_or(0xFF, |cartridge| cartridge.read_rom(address)),
+            Region::VRAM => self.ppu.read_vram(offset),
+            Region::ExternalRAM => self
+                .cartridge
+                .as_ref()
+                .map_or(0xFF, |cartridge| cartridge.read_ram(address)),
+            Region::WRAM | Region::EchoRAM => self.wram[offset],
+            Region::OAM => self.ppu.read_oam(offset),
+            Region::Unusable => 0x00,
+            Region::IO => self.read_io(offset),
+            Region::HRAM => self.hram[offset],
+            Region::IE => self.register_file.borrow().read_u8(Register::IE),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let (region, offset) = Region::decode(address);
+
+        match region {
+            Region::RomBank0 | Region::RomBankN => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.write_rom(address, value);
+                }
+            }
+            Region::VRAM => self.ppu.write_vram(offset, value),
+            Region::ExternalRAM => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.write_ram(address, value);
+                }
+            }
+            Region::WRAM | Region::EchoRAM => self.wram[offset] = value,
+            Region::OAM => self.ppu.write_oam(offset, value),
+            Region::Unusable => {}
+            Region::IO => self.write_io(offset, value),
+            Region::HRAM => self.hram[offset] = value,
+            Region::IE => self
+                .register_file
+                .borrow_mut()
+                .write_u8(Register::IE, value),
+        }
+    }
+
+    // Reads on behalf of the CPU, which has to share the buses with a running OAM DMA transfer and the
+    // PPU. Reading from the bus the DMA reads from returns the byte the DMA is transferring. Unlike a read
+    // through the buses, this has no side effects
+    pub fn cpu_read(&self, address: u16) -> u8 {
+        if let Some(source) = self.dma.source_address() {
+            if Region::decode(address).0 == Region::OAM {
+                return 0xFF;
+            }
+            if MemoryBus::of(address).is_some() && MemoryBus::of(address) == MemoryBus::of(source) {
+                return self.read(source);
+            }
+        }
+        if self.ppu_blocks(address) {
+            return 0xFF;
+        }
+        self.read(address)
+    }
+
+    // Writes on behalf of the CPU. Writes to OAM or to the bus the DMA reads from are lost, as are writes
+    // to the memory the PPU is using
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let Some(source) = self.dma.source_address()
+            && (Region::decode(address).0 == Region::OAM
+                || (MemoryBus::of(address).is_some()
+                    && MemoryBus::of(address) == MemoryBus::of(source)))
+        {
+            return;
+        }
+        if self.ppu_blocks(address) {
+            return;
+        }
+        self.write(address, value);
+    }
+
+    fn ppu_blocks(&self, address: u16) -> bool {
+        match Region::decode(address).0 {
+            Region::VRAM => self.ppu.vram_blocked(),
+            Region::OAM => self.ppu.oam_blocked(),
+            _ => false,
+        }
+    }
+
+    fn read_io(&self, offset: usize) -> u8 {
+        match offset {
+            P1 => self.joypad.read(),
+            SB => self.serial.read_sb(),
+            SC => self.serial.read_sc(),
+            DIV => self.timer.read_div(),
+            TIMA => self.timer.read_tima(),
+            TMA => self.timer.read_tma(),
+            TAC => self.timer.read_tac(),
+            IF => self.interrupt_flags.borrow().read(),
+            NR10..=NR52 | WAVE_RAM_START..=WAVE_RAM_END => self.apu.read_register(offset),
+            DMA => self.dma.read(),
+            LCDC..=WX | OPRI => self.ppu.read_register(offset),
+            KEY1 if self.model == Model::CGB => {
+                0x7E | ((self.double_speed as u8) << 7) | self.speed_switch_armed as u8
+            }
+            KEY1 => 0xFF,
+            _ => self.io[offset],
+        }
+    }
+
+    fn write_io(&mut self, offset: usize, value: u8) {
+        match offset {
+            P1 => self.joypad.write(value),
+            SB => self.serial.write_sb(value),
+            SC => self.serial.write_sc(value),
+            IF => self.interrupt_flags.borrow_mut().write(value),
+            NR10..=NR52 | WAVE_RAM_START..=WAVE_RAM_END => self.apu.write_register(offset, value),
+            DMA => self.dma.write(value),
+            LCDC..=WX | OPRI => self.ppu.write_register(offset, value),
+            DIV => self.write_div(),
+            TIMA => self.timer.write_tima(value),
+            TMA => self.timer.write_tma(value),
+            TAC => self.timer.write_tac(value),
+            KEY1 => self.speed_switch_armed = self.model == Model::CGB && (value & 0x01) == 0x01,
+            _ => self.io[offset] = value,
+        }
+    }
+
+    // Answers the transaction requested by the control signals on the address bus. On a read, the data
+    // at the address is put onto the data bus, on a write the data on the data bus is stored at the address
+    pub fn respond(&mut self) {
+        let signal = self.address_bus.borrow().signal();
+
+        match signal {
+            Signal::Idle => {}
+            Signal::Read => {
+                self.oam_bug_read |= is_oam_bug_address(self.bus_address());
+                let data = self.cpu_read(self.bus_address());
+                self.data_bus.borrow_mut().write(data);
+            }
+            Signal::Write => {
+                let data = self.data_bus.borrow().read().unwrap_or_else(|| {
+                    println!("WARNING: The data bus should not be empty at this point!");
+                    0
+                });
+                self.oam_bug_write |= is_oam_bug_address(self.bus_address());
+                self.cpu_write(self.bus_address(), data);
+            }
+        }
+    }
+
+    fn bus_address(&self) -> u16 {
+        self.address_bus.borrow().read().unwrap_or_else(|| {
+            println!("WARNING: The address bus should not be empty at this point!");
+            0
+        })
+    }
+}
+
+// OAM and the unusable region behind it
+fn is_oam_bug_address(address: u16) -> bool {
+    (0xFE00..=0xFEFF).contains(&address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A DMG whose PPU reads row 5 of OAM in mode 2 during the next M-cycle. Rows 3-5 hold known words
+    fn mmu() -> MMU {
+        let data_bus = Rc::new(RefCell::new(Bus::new()));
+        let address_bus = Rc::new(RefCell::new(Bus::new()));
+        let register_file = Rc::new(RefCell::new(RegisterFile::new(
+            Rc::clone(&data_bus),
+            Rc::clone(&address_bus),
+        )));
+        let interrupt_flags = Rc::new(RefCell::new(InterruptFlags::new()));
+        let mut mmu = MMU::new(
+            data_bus,
+            address_bus,
+            register_file,
+            interrupt_flags,
+            Model::DMG,
+        );
+
+        let rows: [[u16; 4]; 3] = [
+            [0x1234, 0xAAAA, 0xBBBB, 0xCCCC],
+            [0x00F0, 0x1111, 0x0F0F, 0x2222],
+            [0xFF00, 0x3333, 0x4444, 0x5555],
+        ];
+        for (row, words) in rows.iter().enumerate() {
+            for (index, byte) in words.iter().flat_map(|word| word.to_le_bytes()).enumerate() {
+                mmu.ppu_mut().write_oam((row + 3) * 8 + index, byte);
+            }
+        }
+
+        for _ in 0..5 {
+            mmu.clock_cycle();
+        }
+        mmu
+    }
+
+    fn oam_row(mmu: &MMU, row: usize) -> [u16; 4] {
+        [0, 1, 2, 3].map(|index| {
+            let offset = row * 8 + index * 2;
+            u16::from_le_bytes([mmu.ppu().read_oam(offset), mmu.ppu().read_oam(offset + 1)])
+        })
+    }
+
+    fn access(mmu: &mut MMU, signal: Signal, address: u16) {
+        mmu.address_bus.borrow_mut().write(address);
+        mmu.data_bus.borrow_mut().write(0x00);
+        mmu.address_bus.borrow_mut().assert_signal(signal);
+        mmu.respond();
+    }
+
+    #[test]
+    fn inc_dec_of_oam_address_corrupts_like_write() {
+        // INC rr or DEC rr with rr in 0xFE00-0xFEFF
+        let mut mmu = mmu();
+        mmu.idu_activity(0xFE10);
+        mmu.clock_cycle();
+
+        assert_eq!(oam_row(&mmu, 5), [0x0F00, 0x1111, 0x0F0F, 0x2222]);
+    }
+
+    #[test]
+    fn write_to_oam_address_corrupts() {
+        let mut mmu = mmu();
+        access(&mut mmu, Signal::Write, 0xFEA0);
+        mmu.clock_cycle();
+
+        assert_eq!(oam_row(&mmu, 5), [0x0F00, 0x1111, 0x0F0F, 0x2222]);
+    }
+
+    #[test]
+    fn read_from_oam_address_corrupts() {
+        let mut mmu = mmu();
+        access(&mut mmu, Signal::Read, 0xFEA0);
+        mmu.clock_cycle();
+
+        assert_eq!(oam_row(&mmu, 5), [0x0FF0, 0x1111, 0x0F0F, 0x2222]);
+    }
+
+    #[test]
+    fn read_with_inc_dec_of_oam_address_corrupts() {
+        // POP rr or LD A,(HL+) with the address in 0xFE00-0xFEFF
+        let mut mmu = mmu();
+        access(&mut mmu, Signal::Read, 0xFE10);
+        mmu.idu_activity(0xFE10);
+        mmu.clock_cycle();
+
+        for row in 3..=5 {
+            assert_eq!(oam_row(&mmu, row), [0x0230, 0x1111, 0x0F0F, 0x2222]);
+        }
+    }
+
+    #[test]
+    fn oam_is_not_corrupted_outside_oam_scan() {
+        let mut mmu = mmu();
+        // Mode 2 lasts 20 M-cycles
+        for _ in 0..20 {
+            mmu.clock_cycle();
+        }
+        let before = [3, 4, 5].map(|row| oam_row(&mmu, row));
+
+        mmu.idu_activity(0xFE10);
+        mmu.clock_cycle();
+        assert_eq!([3, 4, 5].map(|row| oam_row(&mmu, row)), before);
+    }
+}
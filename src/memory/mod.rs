@@ -0,0 +1,4 @@
+mod dma;
+mod mmu;
+
+pub use mmu::MMU;
@@ -0,0 +1,49 @@
+/*!
+The emulated Game Boy model. The Game Boy Color (CGB) is backwards compatible with the original Game Boy
+(DMG), but adds features such as the double speed mode. Its colors and the banked VRAM and WRAM are not
+emulated yet, so every game runs on a DMG unless the CGB is explicitly asked for.
+https://gbdev.io/pandocs/CGB_Registers.html
+*/
+
+use crate::cartridge::{CgbSupport, Header};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Model {
+    DMG,
+    CGB,
+}
+
+impl Model {
+    // The model to run the game on, which is only the CGB if it was asked for and the game supports it
+    pub fn for_cartridge(header: &Header, cgb: bool) -> Model {
+        match header.cgb_support {
+            CgbSupport::Enhanced | CgbSupport::Only if cgb => Model::CGB,
+            _ => Model::DMG,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    fn model(cgb_flag: u8, cgb: bool) -> Model {
+        let cartridge = Cartridge::with_code(&[(0x0143, &[cgb_flag])]);
+        Model::for_cartridge(cartridge.header(), cgb)
+    }
+
+    #[test]
+    fn dmg_by_default() {
+        assert_eq!(model(0x00, false), Model::DMG);
+        assert_eq!(model(0x80, false), Model::DMG);
+        assert_eq!(model(0xC0, false), Model::DMG);
+    }
+
+    #[test]
+    fn cgb_if_asked_for_and_supported() {
+        assert_eq!(model(0x00, true), Model::DMG);
+        assert_eq!(model(0x80, true), Model::CGB);
+        assert_eq!(model(0xC0, true), Model::CGB);
+    }
+}
@@ -0,0 +1,267 @@
+/*!
+The FIFO renderer models mode 3 the way the hardware performs it. A fetcher reads the background or window
+tiles in steps of 2 dots each and pushes 8 pixels at a time into the background FIFO, which shifts out one
+pixel per dot to the LCD. The pixels of objects are merged into a separate object FIFO, which is shifted
+out together with the background FIFO and mixed with it. The first fetch of every line is thrown away, the pixels scrolled out by SCX are
+discarded, activating the window restarts the fetcher and every object pauses the output while its tile
+is fetched. This makes the length of mode 3 vary, and registers written during mode 3 affect the pixels
+drawn after the write.
+https://gbdev.io/pandocs/pixel_fifo.html
+*/
+
+use crate::ppu::SCREEN_WIDTH;
+use crate::ppu::object::Object;
+use crate::ppu::registers::{Registers, apply_palette, tile_row_color};
+use crate::ppu::window::Window;
+use std::collections::VecDeque;
+
+// Fetching the tile of an object takes 6 dots, during which no pixels are shifted out
+const OBJECT_FETCH_DOTS: u8 = 6;
+
+// A pixel of the object FIFO, color 0 is transparent
+#[derive(Copy, Clone, Debug)]
+struct ObjectPixel {
+    color: u8,
+    object: Object,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+pub struct FifoRenderer {
+    bg_fifo: VecDeque<u8>,
+    obj_fifo: VecDeque<ObjectPixel>,
+
+    step: FetcherStep,
+    // The dots spent in the current fetcher step
+    step_dots: u8,
+    // The tile column fetched next, relative to the start of the background or window
+    fetcher_x: u8,
+    tile_index: u8,
+    tile_low: u8,
+    tile_high: u8,
+    first_fetch: bool,
+
+    // The next pixel on the LCD
+    x: u8,
+    // The pixels of the first tile that are scrolled out by SCX
+    discard: u8,
+
+    window_active: bool,
+    window_line: u8,
+
+    // The objects of the current line whose tile was not fetched yet, in the order of their priority
+    objects: Vec<Object>,
+    object_fetch_dots: u8,
+    // Whether overlapping objects are ordered by X coordinate, otherwise by OAM index
+    x_priority: bool,
+}
+
+impl FifoRenderer {
+    pub fn new() -> FifoRenderer {
+        FifoRenderer {
+            bg_fifo: VecDeque::with_capacity(16),
+            obj_fifo: VecDeque::with_capacity(8),
+            step: FetcherStep::Tile,
+            step_dots: 0,
+            fetcher_x: 0,
+            tile_index: 0,
+            tile_low: 0,
+            tile_high: 0,
+            first_fetch: true,
+            x: 0,
+            discard: 0,
+            window_active: false,
+            window_line: 0,
+            objects: Vec::new(),
+            object_fetch_dots: 0,
+            x_priority: true,
+        }
+    }
+
+    // Prepares mode 3 of the line LY, with the objects selected during OAM scan
+    pub fn start_line(&mut self, registers: &Registers, objects: &[Object], x_priority: bool) {
+        self.bg_fifo.clear();
+        self.obj_fifo.clear();
+        self.restart_fetcher();
+        self.first_fetch = true;
+        self.x = 0;
+        self.discard = registers.scx % 8;
+        self.window_active = false;
+        self.objects = objects.to_vec();
+        self.object_fetch_dots = 0;
+        self.x_priority = x_priority;
+    }
+
+    // Advances mode 3 by one dot and returns whether the line is complete
+    pub fn tick(
+        &mut self,
+        registers: &Registers,
+        vram: &[u8],
+        window: &mut Window,
+        line: &mut [u8],
+    ) -> bool {
+        if self.object_fetch_dots > 0 {
+            self.object_fetch_dots -= 1;
+            return false;
+        }
+
+        // Reaching the window throws away the fetched background pixels and restarts the fetcher on the
+        // window tile map. A window starting left of the screen discards the pixels off screen instead of
+        // the ones scrolled out by SCX
+        if !self.window_active && window.covers(registers, self.x) {
+            self.window_active = true;
+            self.window_line = window.line();
+            window.mark_drawn();
+            if self.x == 0 {
+                self.discard = window.hidden_pixels(registers);
+            }
+            self.bg_fifo.clear();
+            self.restart_fetcher();
+        }
+
+        // An object starting at the next pixel is fetched as soon as the fetcher has a background tile
+        // ready, which stalls the output until then. The object fetch starts in the same dot
+        if registers.obj_enabled()
+            && let Some(i) = self.objects.iter().position(|o| o.x <= self.x + 8)
+        {
+            if self.step != FetcherStep::Push {
+                self.step_fetcher(registers, vram);
+            }
+            if self.step == FetcherStep::Push {
+                let object = self.objects.remove(i);
+                self.fetch_object(registers, vram, object);
+                self.object_fetch_dots = OBJECT_FETCH_DOTS - 1;
+            }
+            return false;
+        }
+
+        if let Some(color) = self.bg_fifo.pop_front() {
+            if self.discard > 0 {
+                self.discard -= 1;
+            } else {
+                let color = if registers.bg_enabled() { color } else { 0 };
+                let object_pixel = self.obj_fifo.pop_front();
+                line[self.x as usize] = match object_pixel {
+                    // Objects behind the background only show through its color 0
+                    Some(pixel)
+                        if pixel.color != 0
+                            && registers.obj_enabled()
+                            && !(pixel.object.behind_bg() && color != 0) =>
+                    {
+                        apply_palette(pixel.object.palette(registers), pixel.color)
+                    }
+                    _ => apply_palette(registers.bgp, color),
+                };
+                self.x += 1;
+            }
+        }
+        self.step_fetcher(registers, vram);
+
+        self.x as usize == SCREEN_WIDTH
+    }
+
+    fn restart_fetcher(&mut self) {
+        self.step = FetcherStep::Tile;
+        self.step_dots = 0;
+        self.fetcher_x = 0;
+    }
+
+    fn step_fetcher(&mut self, registers: &Registers, vram: &[u8]) {
+        if self.step == FetcherStep::Push {
+            self.push();
+            return;
+        }
+
+        // Every step except pushing takes 2 dots, the memory access happens in the second one
+        self.step_dots += 1;
+        if self.step_dots < 2 {
+            return;
+        }
+        self.step_dots = 0;
+
+        match self.step {
+            FetcherStep::Tile => {
+                let (tile_map, column, row) = if self.window_active {
+                    (
+                        registers.window_tile_map(),
+                        self.fetcher_x,
+                        self.window_line,
+                    )
+                } else {
+                    (
+                        registers.bg_tile_map(),
+                        (registers.scx / 8).wrapping_add(self.fetcher_x) & 0x1F,
+                        registers.ly.wrapping_add(registers.scy),
+                    )
+                };
+                self.tile_index = vram[tile_map + (row as usize / 8) * 32 + column as usize];
+                self.step = FetcherStep::DataLow;
+            }
+            FetcherStep::DataLow => {
+                self.tile_low = vram[self.tile_data_address(registers)];
+                self.step = FetcherStep::DataHigh;
+            }
+            FetcherStep::DataHigh => {
+                self.tile_high = vram[self.tile_data_address(registers) + 1];
+                if self.first_fetch {
+                    self.first_fetch = false;
+                    self.step = FetcherStep::Tile;
+                } else {
+                    self.step = FetcherStep::Push;
+                    self.push();
+                }
+            }
+            FetcherStep::Push => {}
+        }
+    }
+
+    // The fetched pixels are only pushed once the FIFO ran empty
+    fn push(&mut self) {
+        if !self.bg_fifo.is_empty() {
+            return;
+        }
+
+        for x in 0..8 {
+            self.bg_fifo
+                .push_back(tile_row_color(self.tile_low, self.tile_high, x));
+        }
+        self.fetcher_x = self.fetcher_x.wrapping_add(1);
+        self.step = FetcherStep::Tile;
+    }
+
+    // Merges the row of the object on the current line into the object FIFO. Pixels left of the screen are
+    // dropped. Where the FIFO already holds an opaque pixel, it stays unless the new object has priority
+    fn fetch_object(&mut self, registers: &Registers, vram: &[u8], object: Object) {
+        let colors = object.row_colors(vram, registers.ly, registers.obj_height());
+        let hidden = (self.x as usize + 8).saturating_sub(object.x as usize);
+
+        for (i, &color) in colors.iter().skip(hidden).enumerate() {
+            let pixel = ObjectPixel { color, object };
+            match self.obj_fifo.get_mut(i) {
+                Some(current) => {
+                    let replace = current.color == 0
+                        || (!self.x_priority && color != 0 && object.index < current.object.index);
+                    if replace {
+                        *current = pixel;
+                    }
+                }
+                None => self.obj_fifo.push_back(pixel),
+            }
+        }
+    }
+
+    fn tile_data_address(&self, registers: &Registers) -> usize {
+        let row = if self.window_active {
+            self.window_line
+        } else {
+            registers.ly.wrapping_add(registers.scy)
+        };
+        registers.bg_tile_data(self.tile_index) + (row as usize % 8) * 2
+    }
+}
@@ -0,0 +1,10 @@
+mod fifo_renderer;
+mod oam_bug;
+mod object;
+mod ppu;
+mod registers;
+mod scanline_renderer;
+mod window;
+
+pub use oam_bug::OamCorruption;
+pub use ppu::{PPU, RendererKind, SCREEN_HEIGHT, SCREEN_WIDTH};
@@ -0,0 +1,160 @@
+/*!
+On the DMG, the CPU putting an address in 0xFE00-0xFEFF onto the address bus while the PPU scans OAM corrupts
+the row of OAM the PPU is reading. OAM is split into 20 rows of 8 bytes, or 4 16-bit words, and the PPU reads
+one row per M-cycle during mode 2. Reads, writes and the IDU incrementing or decrementing such an address
+each corrupt the row differently, mixing it with the rows before it. The first row is never corrupted.
+https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+*/
+
+const ROW_SIZE: usize = 8;
+const ROW_COUNT: usize = 20;
+
+// The bus activity of the CPU in an M-cycle that corrupts OAM
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OamCorruption {
+    // A write, or the IDU incrementing or decrementing without an access
+    Write,
+    Read,
+    // A read while the IDU increments or decrements the same address, as done by POP or LD A,(HL+)
+    ReadWithIncDec,
+}
+
+// Corrupts the given row of OAM, which the PPU is reading in this M-cycle
+pub fn corrupt(oam: &mut [u8], row: usize, corruption: OamCorruption) {
+    if row == 0 || row >= ROW_COUNT {
+        return;
+    }
+
+    match corruption {
+        OamCorruption::Write => {
+            corrupt_first_word(oam, row, |a, b, c| ((a ^ c) & (b ^ c)) ^ c);
+        }
+        OamCorruption::Read => {
+            corrupt_first_word(oam, row, |a, b, c| b | (a & c));
+        }
+        OamCorruption::ReadWithIncDec => {
+            // The row before is corrupted as well and copied over the current row and the one before it.
+            // Neither happens for the first four rows and the last one
+            if (4..ROW_COUNT - 1).contains(&row) {
+                let a = word(oam, row - 2, 0);
+                let b = word(oam, row - 1, 0);
+                let c = word(oam, row, 0);
+                let d = word(oam, row - 1, 2);
+                set_word(oam, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+
+                copy_row(oam, row - 1, row);
+                copy_row(oam, row - 1, row - 2);
+            }
+            corrupt_first_word(oam, row, |a, b, c| b | (a & c));
+        }
+    }
+}
+
+// Replaces the first word of the row by a combination of itself and the first and third word of the row
+// before, and copies the other three words from the row before
+fn corrupt_first_word(oam: &mut [u8], row: usize, combine: impl Fn(u16, u16, u16) -> u16) {
+    let a = word(oam, row, 0);
+    let b = word(oam, row - 1, 0);
+    let c = word(oam, row - 1, 2);
+    set_word(oam, row, 0, combine(a, b, c));
+
+    let start = row * ROW_SIZE;
+    oam.copy_within(start - ROW_SIZE + 2..start, start + 2);
+}
+
+fn copy_row(oam: &mut [u8], from: usize, to: usize) {
+    oam.copy_within(from * ROW_SIZE..(from + 1) * ROW_SIZE, to * ROW_SIZE);
+}
+
+fn word(oam: &[u8], row: usize, index: usize) -> u16 {
+    let offset = row * ROW_SIZE + index * 2;
+    u16::from_le_bytes([oam[offset], oam[offset + 1]])
+}
+
+fn set_word(oam: &mut [u8], row: usize, index: usize, value: u16) {
+    let offset = row * ROW_SIZE + index * 2;
+    oam[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OAM_SIZE: usize = ROW_SIZE * ROW_COUNT;
+
+    // OAM with known words in rows 3-5, the rows around row 5 which the PPU reads in these tests
+    fn oam() -> [u8; OAM_SIZE] {
+        let mut oam = [0; OAM_SIZE];
+        set_row(&mut oam, 3, [0x1234, 0xAAAA, 0xBBBB, 0xCCCC]);
+        set_row(&mut oam, 4, [0x00F0, 0x1111, 0x0F0F, 0x2222]);
+        set_row(&mut oam, 5, [0xFF00, 0x3333, 0x4444, 0x5555]);
+        oam
+    }
+
+    fn set_row(oam: &mut [u8], row: usize, words: [u16; 4]) {
+        for (index, value) in words.into_iter().enumerate() {
+            set_word(oam, row, index, value);
+        }
+    }
+
+    fn row(oam: &[u8], row: usize) -> [u16; 4] {
+        [0, 1, 2, 3].map(|index| word(oam, row, index))
+    }
+
+    #[test]
+    fn write_corruption() {
+        let mut oam = oam();
+        corrupt(&mut oam, 5, OamCorruption::Write);
+
+        // ((a ^ c) & (b ^ c)) ^ c, followed by the rest of the row before
+        assert_eq!(row(&oam, 5), [0x0F00, 0x1111, 0x0F0F, 0x2222]);
+        assert_eq!(row(&oam, 4), [0x00F0, 0x1111, 0x0F0F, 0x2222]);
+        assert_eq!(row(&oam, 3), [0x1234, 0xAAAA, 0xBBBB, 0xCCCC]);
+    }
+
+    #[test]
+    fn read_corruption() {
+        let mut oam = oam();
+        corrupt(&mut oam, 5, OamCorruption::Read);
+
+        // b | (a & c), followed by the rest of the row before
+        assert_eq!(row(&oam, 5), [0x0FF0, 0x1111, 0x0F0F, 0x2222]);
+        assert_eq!(row(&oam, 4), [0x00F0, 0x1111, 0x0F0F, 0x2222]);
+        assert_eq!(row(&oam, 3), [0x1234, 0xAAAA, 0xBBBB, 0xCCCC]);
+    }
+
+    #[test]
+    fn read_with_inc_dec_corruption() {
+        let mut oam = oam();
+        corrupt(&mut oam, 5, OamCorruption::ReadWithIncDec);
+
+        // The first word of the row before becomes (b & (a | c | d)) | (a & c & d) and the row before is
+        // copied to both of its neighbours, before the read corruption is applied
+        assert_eq!(row(&oam, 5), [0x0230, 0x1111, 0x0F0F, 0x2222]);
+        assert_eq!(row(&oam, 4), [0x0230, 0x1111, 0x0F0F, 0x2222]);
+        assert_eq!(row(&oam, 3), [0x0230, 0x1111, 0x0F0F, 0x2222]);
+    }
+
+    #[test]
+    fn read_with_inc_dec_corruption_in_first_rows() {
+        let mut oam = oam();
+        corrupt(&mut oam, 3, OamCorruption::ReadWithIncDec);
+
+        // Only the read corruption is applied to rows 1-3
+        let mut expected = self::oam();
+        corrupt(&mut expected, 3, OamCorruption::Read);
+        assert_eq!(oam, expected);
+    }
+
+    #[test]
+    fn first_row_is_never_corrupted() {
+        let mut oam = oam();
+        set_row(&mut oam, 0, [0x0102, 0x0304, 0x0506, 0x0708]);
+        let expected = oam;
+
+        corrupt(&mut oam, 0, OamCorruption::Write);
+        corrupt(&mut oam, 0, OamCorruption::Read);
+        corrupt(&mut oam, 0, OamCorruption::ReadWithIncDec);
+        assert_eq!(oam, expected);
+    }
+}
@@ -0,0 +1,112 @@
+/*!
+Objects (OBJ), also called sprites, are described by 4 bytes each in OAM: their Y and X position, offset by
+16 and 8 pixels so they can be moved partially off screen, their tile index and their attributes. During
+OAM scan, the PPU selects the objects that overlap the current line. Where objects overlap, the DMG draws
+the one with the smaller X coordinate on top, while the CGB draws the one first in OAM, unless OPRI
+selects the DMG behavior.
+https://gbdev.io/pandocs/OAM.html
+*/
+
+use crate::ppu::registers::{Registers, tile_row_color};
+
+pub const OBJECT_COUNT: usize = 40;
+// The PPU only draws up to 10 objects per line
+pub const OBJECTS_PER_LINE: usize = 10;
+
+// Bits of the attributes
+const BEHIND_BG: u8 = 0x80;
+const Y_FLIP: u8 = 0x40;
+const X_FLIP: u8 = 0x20;
+const DMG_PALETTE: u8 = 0x10;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Object {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    // The position of the object in OAM
+    pub index: u8,
+}
+
+impl Object {
+    pub fn from_oam(oam: &[u8], index: usize) -> Object {
+        Object {
+            y: oam[index * 4],
+            x: oam[index * 4 + 1],
+            tile: oam[index * 4 + 2],
+            attributes: oam[index * 4 + 3],
+            index: index as u8,
+        }
+    }
+
+    // Selects the objects that overlap the line LY, in the order of OAM. Without the limit, all of them are
+    // selected, which avoids the flickering games use to show more than 10 objects on a line
+    pub fn scan(oam: &[u8], ly: u8, height: u8, limit: bool) -> Vec<Object> {
+        let limit = if limit {
+            OBJECTS_PER_LINE
+        } else {
+            OBJECT_COUNT
+        };
+
+        (0..OBJECT_COUNT)
+            .map(|index| Object::from_oam(oam, index))
+            .filter(|object| {
+                let line = ly as u16 + 16;
+                line >= object.y as u16 && line < object.y as u16 + height as u16
+            })
+            .take(limit)
+            .collect()
+    }
+
+    // Sorts the objects so that the one drawn on top of overlapping objects comes first. The DMG prefers
+    // the object further left and only then the one first in OAM, the CGB only goes by the order in OAM
+    pub fn sort_by_priority(objects: &mut [Object], x_priority: bool) {
+        if x_priority {
+            objects.sort_by_key(|object| (object.x, object.index));
+        } else {
+            objects.sort_by_key(|object| object.index);
+        }
+    }
+
+    // Whether background and window colors 1-3 are drawn over the object
+    pub fn behind_bg(&self) -> bool {
+        (self.attributes & BEHIND_BG) == BEHIND_BG
+    }
+
+    // The DMG palette of the object, OBP0 or OBP1
+    pub fn palette(&self, registers: &Registers) -> u8 {
+        if (self.attributes & DMG_PALETTE) == DMG_PALETTE {
+            registers.obp1
+        } else {
+            registers.obp0
+        }
+    }
+
+    // The color indices of the row of the object on the line LY, from left to right. Color 0 is transparent
+    pub fn row_colors(&self, vram: &[u8], ly: u8, height: u8) -> [u8; 8] {
+        let mut row = (ly as u16 + 16 - self.y as u16) as u8;
+        if (self.attributes & Y_FLIP) == Y_FLIP {
+            row = height - 1 - row;
+        }
+
+        // Objects are always taken from 0x8000-0x8FFF. Tall objects consist of an even and an odd tile
+        let tile = if height == 16 {
+            self.tile & 0xFE
+        } else {
+            self.tile
+        };
+        let address = tile as usize * 16 + row as usize * 2;
+
+        let mut colors = [0; 8];
+        for (x, color) in colors.iter_mut().enumerate() {
+            let x = if (self.attributes & X_FLIP) == X_FLIP {
+                7 - x
+            } else {
+                x
+            };
+            *color = tile_row_color(vram[address], vram[address + 1], x as u8);
+        }
+        colors
+    }
+}
@@ -0,0 +1,459 @@
+/*!
+The Picture Processing Unit (PPU) draws the 160x144 pixel picture line by line. Each line takes 456 dots,
+during which the PPU searches OAM for objects (mode 2), draws the pixels (mode 3) and then idles until the
+end of the line (mode 0, HBlank). After 144 lines, it idles for another 10 lines (mode 1, VBlank), in which
+the game usually prepares the next frame. The PPU requests the VBlank interrupt when entering VBlank, and
+the STAT interrupt on the rising edge of any condition selected in STAT.
+https://gbdev.io/pandocs/Rendering.html
+*/
+
+use crate::interrupts::{Interrupt, InterruptFlags};
+use crate::model::Model;
+use crate::ppu::fifo_renderer::FifoRenderer;
+use crate::ppu::oam_bug::{self, OamCorruption};
+use crate::ppu::object::Object;
+use crate::ppu::registers::Registers;
+use crate::ppu::scanline_renderer::ScanlineRenderer;
+use crate::ppu::window::Window;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+const VRAM_SIZE: usize = 0x2000;
+const OAM_SIZE: usize = 0xA0;
+
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+const DRAWING_DOTS: u16 = 172;
+// Restarting the fetcher for the window delays mode 3
+const WINDOW_DOTS: u16 = 6;
+// Fetching an object delays mode 3, and more so if the fetcher has to finish a background tile first
+const OBJECT_DOTS: u16 = 6;
+const OBJECT_X0_DOTS: u16 = 11;
+const LINES_PER_FRAME: u8 = 154;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+// The FIFO renderer is accurate down to single dots, the scanline renderer is faster
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RendererKind {
+    Fifo,
+    Scanline,
+}
+
+enum Renderer {
+    Fifo(FifoRenderer),
+    Scanline(ScanlineRenderer),
+}
+
+impl Renderer {
+    fn new(kind: RendererKind) -> Renderer {
+        match kind {
+            RendererKind::Fifo => Renderer::Fifo(FifoRenderer::new()),
+            RendererKind::Scanline => Renderer::Scanline(ScanlineRenderer::new()),
+        }
+    }
+}
+
+pub struct PPU {
+    interrupt_flags: Rc<RefCell<InterruptFlags>>,
+    model: Model,
+
+    registers: Registers,
+    vram: [u8; VRAM_SIZE],
+    oam: [u8; OAM_SIZE],
+    // 0xFF6C, CGB only, bit 0 orders overlapping objects by X coordinate like the DMG
+    opri: u8,
+
+    mode: Mode,
+    // The dot inside the current line
+    dot: u16,
+    // The length of mode 3 in the current line, as far as the scanline renderer is concerned
+    drawing_dots: u16,
+    // The objects selected by the OAM scan of the current line, in the order of their priority
+    objects: Vec<Object>,
+    // Without the limit of 10 objects per line, games that flicker objects to work around it draw all of
+    // them in every frame
+    sprite_limit: bool,
+    // Whether the CPU is locked out of VRAM and OAM while the PPU uses them. Turning this off helps to
+    // debug games, but hides their timing bugs
+    access_blocking: bool,
+    window: Window,
+    // The OR of all conditions selected in STAT, the interrupt is only requested when it rises
+    stat_line: bool,
+
+    // The shades 0-3 of every pixel, from white to black
+    framebuffer: Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+    frame_ready: bool,
+    renderer: Renderer,
+}
+
+impl PPU {
+    pub fn new(interrupt_flags: Rc<RefCell<InterruptFlags>>, model: Model) -> PPU {
+        PPU {
+            interrupt_flags,
+            model,
+            registers: Registers::new(),
+            vram: [0; VRAM_SIZE],
+            oam: [0; OAM_SIZE],
+            opri: 0,
+            mode: Mode::OamScan,
+            dot: 0,
+            drawing_dots: DRAWING_DOTS,
+            objects: Vec::new(),
+            sprite_limit: true,
+            access_blocking: true,
+            window: Window::new(),
+            stat_line: false,
+            framebuffer: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]),
+            frame_ready: false,
+            renderer: Renderer::new(RendererKind::Fifo),
+        }
+    }
+
+    pub fn set_renderer(&mut self, kind: RendererKind) {
+        self.renderer = Renderer::new(kind);
+    }
+
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+    }
+
+    pub fn set_access_blocking(&mut self, enabled: bool) {
+        self.access_blocking = enabled;
+    }
+
+    // VRAM is read by the PPU during mode 3, so the CPU reads 0xFF and its writes are lost
+    pub fn vram_blocked(&self) -> bool {
+        self.access_blocking && self.registers.lcd_enabled() && self.mode == Mode::Drawing
+    }
+
+    // OAM is read by the PPU during modes 2 and 3, so the CPU reads 0xFF and its writes are lost
+    pub fn oam_blocked(&self) -> bool {
+        self.access_blocking
+            && self.registers.lcd_enabled()
+            && (self.mode == Mode::OamScan || self.mode == Mode::Drawing)
+    }
+
+    // The CPU accessing 0xFE00-0xFEFF during mode 2 corrupts the row of OAM read in this M-cycle. Only
+    // the DMG has this bug
+    pub fn corrupt_oam(&mut self, corruption: OamCorruption) {
+        if self.registers.lcd_enabled() && self.mode == Mode::OamScan {
+            oam_bug::corrupt(&mut self.oam, (self.dot / 4) as usize, corruption);
+        }
+    }
+
+    pub fn framebuffer(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.framebuffer
+    }
+
+    // Returns whether a new frame was completed since the last call, and resets the flag
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::replace(&mut self.frame_ready, false)
+    }
+
+    pub fn read_vram(&self, offset: usize) -> u8 {
+        self.vram[offset]
+    }
+
+    pub fn write_vram(&mut self, offset: usize, value: u8) {
+        self.vram[offset] = value;
+    }
+
+    pub fn read_oam(&self, offset: usize) -> u8 {
+        self.oam[offset]
+    }
+
+    pub fn write_oam(&mut self, offset: usize, value: u8) {
+        self.oam[offset] = value;
+    }
+
+    // Reads the LCD register at the given offset inside the IO region
+    pub fn read_register(&self, offset: usize) -> u8 {
+        match offset {
+            0x40 => self.registers.lcdc,
+            0x41 => {
+                let coincidence = ((self.registers.ly == self.registers.lyc) as u8) << 2;
+                0x80 | self.registers.stat | coincidence | self.stat_mode()
+            }
+            0x42 => self.registers.scy,
+            0x43 => self.registers.scx,
+            0x44 => self.registers.ly,
+            0x45 => self.registers.lyc,
+            0x47 => self.registers.bgp,
+            0x48 => self.registers.obp0,
+            0x49 => self.registers.obp1,
+            0x4A => self.registers.wy,
+            0x4B => self.registers.wx,
+            0x6C if self.model == Model::CGB => 0xFE | self.opri,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, offset: usize, value: u8) {
+        match offset {
+            0x40 => {
+                let was_enabled = self.registers.lcd_enabled();
+                self.registers.lcdc = value;
+                if was_enabled && !self.registers.lcd_enabled() {
+                    self.turn_off();
+                } else if !was_enabled && self.registers.lcd_enabled() {
+                    self.turn_on();
+                }
+            }
+            0x41 => self.registers.stat = value & 0x78,
+            0x42 => self.registers.scy = value,
+            0x43 => self.registers.scx = value,
+            // LY is read-only
+            0x44 => {}
+            0x45 => self.registers.lyc = value,
+            0x47 => self.registers.bgp = value,
+            0x48 => self.registers.obp0 = value,
+            0x49 => self.registers.obp1 = value,
+            0x4A => self.registers.wy = value,
+            0x4B => self.registers.wx = value,
+            0x6C if self.model == Model::CGB => self.opri = value & 0x01,
+            _ => {}
+        }
+        self.update_stat_line();
+    }
+
+    // Advances the PPU by one dot, which always lasts one T-cycle at normal speed
+    pub fn tick(&mut self) {
+        if !self.registers.lcd_enabled() {
+            return;
+        }
+
+        self.dot += 1;
+        match self.mode {
+            Mode::OamScan => {
+                if self.dot == OAM_SCAN_DOTS {
+                    self.objects = Object::scan(
+                        &self.oam,
+                        self.registers.ly,
+                        self.registers.obj_height(),
+                        self.sprite_limit,
+                    );
+                    let x_priority = self.x_priority();
+                    Object::sort_by_priority(&mut self.objects, x_priority);
+                    self.start_drawing();
+                }
+            }
+            Mode::Drawing => {
+                if self.draw() {
+                    self.mode = Mode::HBlank;
+                }
+            }
+            Mode::HBlank => {
+                // Without the sprite limit, the objects can delay mode 3 past the end of the line
+                if self.dot >= DOTS_PER_LINE {
+                    self.window.end_line();
+                    self.next_line();
+                    if self.registers.ly as usize == SCREEN_HEIGHT {
+                        self.mode = Mode::VBlank;
+                        self.frame_ready = true;
+                        self.interrupt_flags.borrow_mut().request(Interrupt::VBlank);
+                    } else {
+                        self.mode = Mode::OamScan;
+                    }
+                }
+            }
+            Mode::VBlank => {
+                if self.dot == DOTS_PER_LINE {
+                    self.next_line();
+                    if self.registers.ly == 0 {
+                        self.start_frame();
+                        self.mode = Mode::OamScan;
+                    }
+                }
+            }
+        }
+
+        self.update_stat_line();
+    }
+
+    fn next_line(&mut self) {
+        self.dot = 0;
+        self.registers.ly = (self.registers.ly + 1) % LINES_PER_FRAME;
+    }
+
+    // Whether overlapping objects are ordered by X coordinate, otherwise by OAM index
+    fn x_priority(&self) -> bool {
+        self.model == Model::DMG || (self.opri & 0x01) == 0x01
+    }
+
+    fn start_frame(&mut self) {
+        self.window.start_frame();
+    }
+
+    fn start_drawing(&mut self) {
+        self.mode = Mode::Drawing;
+        self.window.start_line(&self.registers);
+
+        let x_priority = self.x_priority();
+        match &mut self.renderer {
+            Renderer::Fifo(renderer) => {
+                renderer.start_line(&self.registers, &self.objects, x_priority)
+            }
+            Renderer::Scanline(_) => {
+                // Fine scrolling discards the first pixels of the first tile, delaying mode 3. A window
+                // starting at the left edge discards its hidden pixels instead, while a window starting
+                // later restarts the fetcher
+                self.drawing_dots = if self.window.covers(&self.registers, 0) {
+                    DRAWING_DOTS + self.window.hidden_pixels(&self.registers) as u16
+                } else if self
+                    .window
+                    .covers(&self.registers, (SCREEN_WIDTH - 1) as u8)
+                {
+                    DRAWING_DOTS + (self.registers.scx % 8) as u16 + WINDOW_DOTS
+                } else {
+                    DRAWING_DOTS + (self.registers.scx % 8) as u16
+                } + self.object_dots();
+            }
+        }
+    }
+
+    // Estimates how much the objects of the current line delay mode 3. Every object takes 6 dots, plus the
+    // dots the fetcher needs to finish the background tile under the left edge of the first object on it
+    fn object_dots(&self) -> u16 {
+        if !self.registers.obj_enabled() {
+            return 0;
+        }
+
+        let mut objects = self.objects.clone();
+        objects.sort_by_key(|object| object.x);
+
+        let mut dots = 0;
+        let mut last_tile = None;
+        for object in objects
+            .iter()
+            .filter(|object| (object.x as usize) < SCREEN_WIDTH + 8)
+        {
+            if object.x == 0 {
+                dots += OBJECT_X0_DOTS;
+                continue;
+            }
+
+            let position = object.x as u16 + (self.registers.scx % 8) as u16;
+            if last_tile != Some(position / 8) {
+                last_tile = Some(position / 8);
+                dots += 5 - (position % 8).min(5);
+            }
+            dots += OBJECT_DOTS;
+        }
+        dots
+    }
+
+    // Advances mode 3 by one dot and returns whether the line is complete
+    fn draw(&mut self) -> bool {
+        let start = self.registers.ly as usize * SCREEN_WIDTH;
+        let line = &mut self.framebuffer[start..start + SCREEN_WIDTH];
+
+        match &mut self.renderer {
+            Renderer::Fifo(renderer) => {
+                renderer.tick(&self.registers, &self.vram, &mut self.window, line)
+            }
+            Renderer::Scanline(renderer) => {
+                // The whole line is drawn at the end of mode 3
+                let complete = self.dot == OAM_SCAN_DOTS + self.drawing_dots;
+                if complete {
+                    renderer.render_line(
+                        &self.registers,
+                        &self.vram,
+                        &mut self.window,
+                        &self.objects,
+                        line,
+                    );
+                }
+                complete
+            }
+        }
+    }
+
+    // While the LCD is off, LY stays at 0 and STAT reports HBlank. The screen turns blank
+    fn turn_off(&mut self) {
+        self.registers.ly = 0;
+        self.dot = 0;
+        self.mode = Mode::HBlank;
+        self.framebuffer.fill(0);
+        self.frame_ready = true;
+    }
+
+    fn turn_on(&mut self) {
+        self.registers.ly = 0;
+        self.dot = 0;
+        self.mode = Mode::OamScan;
+        self.start_frame();
+    }
+
+    // The mode reported in STAT, which is 0 while the LCD is off
+    fn stat_mode(&self) -> u8 {
+        if self.registers.lcd_enabled() {
+            self.mode as u8
+        } else {
+            0
+        }
+    }
+
+    // The STAT interrupt is requested only when the OR of the selected conditions rises. As long as one
+    // condition holds, others becoming true do not request it again, which is known as STAT blocking
+    fn update_stat_line(&mut self) {
+        let stat = self.registers.stat;
+        let enabled = self.registers.lcd_enabled();
+        let line = enabled
+            && (((stat & 0x40) == 0x40 && self.registers.ly == self.registers.lyc)
+                || ((stat & 0x20) == 0x20 && self.mode == Mode::OamScan)
+                || ((stat & 0x10) == 0x10 && self.mode == Mode::VBlank)
+                || ((stat & 0x08) == 0x08 && self.mode == Mode::HBlank));
+
+        if line && !self.stat_line {
+            self.interrupt_flags.borrow_mut().request(Interrupt::STAT);
+        }
+        self.stat_line = line;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Puts all 40 objects on the first 8 lines and runs a frame without the sprite limit
+    fn run_frame_with_all_objects(kind: RendererKind) {
+        let interrupt_flags = Rc::new(RefCell::new(InterruptFlags::new()));
+        let mut ppu = PPU::new(interrupt_flags, Model::DMG);
+        ppu.set_renderer(kind);
+        ppu.set_sprite_limit(false);
+        for index in 0..OAM_SIZE / 4 {
+            ppu.write_oam(index * 4, 16);
+            ppu.write_oam(index * 4 + 1, 8 + index as u8 * 4);
+        }
+        // LCD, objects and background on
+        ppu.write_register(0x40, 0x83);
+
+        let mut dots = 0;
+        while !ppu.take_frame_ready() {
+            ppu.tick();
+            dots += 1;
+            assert!(dots < 2 * LINES_PER_FRAME as u32 * DOTS_PER_LINE as u32);
+        }
+        assert_eq!(ppu.read_register(0x44), SCREEN_HEIGHT as u8);
+    }
+
+    #[test]
+    fn fifo_line_with_all_objects_ends() {
+        run_frame_with_all_objects(RendererKind::Fifo);
+    }
+
+    #[test]
+    fn scanline_line_with_all_objects_ends() {
+        run_frame_with_all_objects(RendererKind::Scanline);
+    }
+}
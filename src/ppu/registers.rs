@@ -0,0 +1,108 @@
+/*!
+The LCD registers at 0xFF40-0xFF4B control how the PPU draws the background, the window and the objects,
+and report the state of the PPU back to the CPU.
+https://gbdev.io/pandocs/LCDC.html
+*/
+
+pub struct Registers {
+    // 0xFF40, LCD Control
+    pub lcdc: u8,
+    // 0xFF41, LCD Status, only the interrupt select bits 3-6 are stored
+    pub stat: u8,
+    // 0xFF42, 0xFF43, Background viewport position
+    pub scy: u8,
+    pub scx: u8,
+    // 0xFF44, the line being drawn, read-only
+    pub ly: u8,
+    // 0xFF45, compared against LY
+    pub lyc: u8,
+    // 0xFF47-0xFF49, DMG palettes
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    // 0xFF4A, 0xFF4B, Window position
+    pub wy: u8,
+    pub wx: u8,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers {
+            lcdc: 0x91,
+            stat: 0x00,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+        }
+    }
+
+    // LCDC bit 7
+    pub fn lcd_enabled(&self) -> bool {
+        (self.lcdc & 0x80) == 0x80
+    }
+
+    // LCDC bit 6, the VRAM offset of the tile map used by the window
+    pub fn window_tile_map(&self) -> usize {
+        if (self.lcdc & 0x40) == 0x40 {
+            0x1C00
+        } else {
+            0x1800
+        }
+    }
+
+    // LCDC bit 5
+    pub fn window_enabled(&self) -> bool {
+        (self.lcdc & 0x20) == 0x20
+    }
+
+    // LCDC bit 3, the VRAM offset of the tile map used by the background
+    pub fn bg_tile_map(&self) -> usize {
+        if (self.lcdc & 0x08) == 0x08 {
+            0x1C00
+        } else {
+            0x1800
+        }
+    }
+
+    // LCDC bit 2
+    pub fn obj_height(&self) -> u8 {
+        if (self.lcdc & 0x04) == 0x04 { 16 } else { 8 }
+    }
+
+    // LCDC bit 1
+    pub fn obj_enabled(&self) -> bool {
+        (self.lcdc & 0x02) == 0x02
+    }
+
+    // LCDC bit 0, on the DMG the background and window are blank while it is cleared
+    pub fn bg_enabled(&self) -> bool {
+        (self.lcdc & 0x01) == 0x01
+    }
+
+    // LCDC bit 4 selects how background and window tiles are addressed. Either tiles 0-255 are taken from
+    // 0x8000-0x8FFF, or tiles -128-127 relative to 0x9000. Returns the VRAM offset of the tile data
+    pub fn bg_tile_data(&self, tile_index: u8) -> usize {
+        if (self.lcdc & 0x10) == 0x10 {
+            tile_index as usize * 16
+        } else {
+            (0x1000 + (tile_index as i8 as isize) * 16) as usize
+        }
+    }
+}
+
+// Translates a 2-bit color index into a shade through a DMG palette register
+pub fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0x03
+}
+
+// The 2-bit color index of pixel x in a row of 8 pixels, whose bit planes are stored in two consecutive bytes
+pub fn tile_row_color(low: u8, high: u8, x: u8) -> u8 {
+    let bit = 7 - x;
+    (((high >> bit) & 0x01) << 1) | ((low >> bit) & 0x01)
+}
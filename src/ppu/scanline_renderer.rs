@@ -0,0 +1,84 @@
+/*!
+The scanline renderer draws a whole line at once, at the end of mode 3. It is fast, but only sees the
+registers as they are at that point, so mid-scanline effects are not visible.
+https://gbdev.io/pandocs/Rendering.html
+*/
+
+use crate::ppu::object::Object;
+use crate::ppu::registers::{Registers, apply_palette, tile_row_color};
+use crate::ppu::window::Window;
+
+pub struct ScanlineRenderer {}
+
+impl ScanlineRenderer {
+    pub fn new() -> ScanlineRenderer {
+        ScanlineRenderer {}
+    }
+
+    // Draws the line LY into the given line of the framebuffer, with the objects selected during OAM scan
+    // in the order of their priority
+    pub fn render_line(
+        &mut self,
+        registers: &Registers,
+        vram: &[u8],
+        window: &mut Window,
+        objects: &[Object],
+        line: &mut [u8],
+    ) {
+        let rows: Vec<[u8; 8]> = objects
+            .iter()
+            .map(|object| object.row_colors(vram, registers.ly, registers.obj_height()))
+            .collect();
+
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let x = x as u8;
+            let color = if !registers.bg_enabled() {
+                0
+            } else if window.covers(registers, x) {
+                window.mark_drawn();
+                self.window_color(registers, vram, window, x)
+            } else {
+                self.bg_color(registers, vram, x)
+            };
+
+            // The first opaque object pixel wins, even if it is then hidden behind the background
+            let object_pixel = objects
+                .iter()
+                .zip(&rows)
+                .filter(|_| registers.obj_enabled())
+                .find_map(|(object, row)| {
+                    let column = (x as usize + 8).checked_sub(object.x as usize)?;
+                    let color = *row.get(column)?;
+                    (color != 0).then_some((object, color))
+                });
+
+            *pixel = match object_pixel {
+                Some((object, object_color)) if !(object.behind_bg() && color != 0) => {
+                    apply_palette(object.palette(registers), object_color)
+                }
+                _ => apply_palette(registers.bgp, color),
+            };
+        }
+    }
+
+    fn bg_color(&self, registers: &Registers, vram: &[u8], x: u8) -> u8 {
+        let x = x.wrapping_add(registers.scx);
+        let y = registers.ly.wrapping_add(registers.scy);
+
+        let tile_index = vram[registers.bg_tile_map() + (y as usize / 8) * 32 + (x as usize / 8)];
+        let address = registers.bg_tile_data(tile_index) + (y as usize % 8) * 2;
+
+        tile_row_color(vram[address], vram[address + 1], x % 8)
+    }
+
+    fn window_color(&self, registers: &Registers, vram: &[u8], window: &Window, x: u8) -> u8 {
+        let x = (x as u16 + 7 - registers.wx as u16) as u8;
+        let y = window.line();
+
+        let tile_index =
+            vram[registers.window_tile_map() + (y as usize / 8) * 32 + (x as usize / 8)];
+        let address = registers.bg_tile_data(tile_index) + (y as usize % 8) * 2;
+
+        tile_row_color(vram[address], vram[address + 1], x % 8)
+    }
+}
@@ -0,0 +1,69 @@
+/*!
+The window is a second background layer, drawn over the background from the position WX-7, WY on. Once
+LY matched WY during a frame, the window is drawn on every following line of that frame in which it is
+enabled. The line of the window being drawn comes from an internal counter, which only advances on lines
+the window was actually drawn on, so disabling the window for some lines continues it where it left off.
+https://gbdev.io/pandocs/Scrolling.html#window
+*/
+
+use crate::ppu::registers::Registers;
+
+// Larger values of WX move the window completely off screen
+const MAX_WX: u8 = 166;
+
+pub struct Window {
+    // LY matched WY in the current frame
+    wy_triggered: bool,
+    // The internal window line counter
+    line: u8,
+    drawn: bool,
+}
+
+impl Window {
+    pub fn new() -> Window {
+        Window {
+            wy_triggered: false,
+            line: 0,
+            drawn: false,
+        }
+    }
+
+    pub fn start_frame(&mut self) {
+        self.wy_triggered = false;
+        self.line = 0;
+    }
+
+    pub fn start_line(&mut self, registers: &Registers) {
+        if registers.ly == registers.wy {
+            self.wy_triggered = true;
+        }
+        self.drawn = false;
+    }
+
+    pub fn end_line(&mut self) {
+        if self.drawn {
+            self.line = self.line.wrapping_add(1);
+        }
+    }
+
+    // Whether the window covers the given pixel of the current line
+    pub fn covers(&self, registers: &Registers, x: u8) -> bool {
+        registers.window_enabled()
+            && self.wy_triggered
+            && registers.wx <= MAX_WX
+            && x as u16 + 7 >= registers.wx as u16
+    }
+
+    // With WX below 7, the window starts left of the screen and its first pixels are cut off
+    pub fn hidden_pixels(&self, registers: &Registers) -> u8 {
+        7u8.saturating_sub(registers.wx)
+    }
+
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+
+    pub fn mark_drawn(&mut self) {
+        self.drawn = true;
+    }
+}
@@ -0,0 +1,59 @@
+/*!
+A serial device sits on the other end of the link port. The Game Boy exchanges whole bytes with it: when the
+Game Boy drives the clock, the device answers every byte it sends, and when the Game Boy waits for an
+external clock, the device decides when a byte arrives.
+https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+*/
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub trait SerialDevice {
+    // Exchanges the byte the Game Boy shifted out with its internal clock for the byte the device shifted
+    // in at the same time
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    // Called every M-cycle with the byte in SB while the Game Boy waits for a transfer with an external
+    // clock, or None while it does not. Returns the byte the device clocked in, which completes the transfer
+    fn poll(&mut self, _waiting: Option<u8>) -> Option<u8> {
+        None
+    }
+}
+
+// Collects the bytes the Game Boy sends as text, like the output of test ROMs that report their results
+// over the serial port. Nothing is sent back, as if no partner was connected
+pub struct SerialCapture {
+    output: Rc<RefCell<String>>,
+}
+
+impl SerialCapture {
+    pub fn new() -> SerialCapture {
+        SerialCapture {
+            output: Rc::new(RefCell::new(String::new())),
+        }
+    }
+
+    // The text received so far, which keeps growing while the device is connected
+    pub fn output(&self) -> Rc<RefCell<String>> {
+        Rc::clone(&self.output)
+    }
+}
+
+impl SerialDevice for SerialCapture {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.output.borrow_mut().push(byte as char);
+        0xFF
+    }
+}
+
+// Whether a test ROM passed, judging by the text it sent so far. Test ROMs like Blargg's print their name,
+// the failed tests and then Passed or Failed
+pub fn test_result(output: &str) -> Option<bool> {
+    if output.contains("Passed") {
+        Some(true)
+    } else if output.contains("Failed") {
+        Some(false)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,65 @@
+/*!
+A link cable connects the serial ports of two emulators in the same process. The Game Boy driving the
+clock is the master: at the end of its transfer, it receives the byte of the other Game Boy if that one waits
+for a transfer with an external clock, and the other Game Boy completes its transfer with the byte of the
+master at the same time. Without a waiting partner, the master reads 0xFF, as if nothing was connected.
+The emulators have to be run in lockstep, one M-cycle each in turn, so the bytes are exchanged when both
+Game Boys would see them on hardware.
+https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+*/
+
+use crate::serial::SerialDevice;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Default)]
+struct Port {
+    // The byte in SB while the Game Boy on this side waits for a transfer with an external clock
+    waiting: Option<u8>,
+    // The byte a master on the other side sent to this side
+    received: Option<u8>,
+}
+
+// One of the two plugs of the cable, connected to the serial port of an emulator
+pub struct LinkCableEnd {
+    ports: Rc<RefCell<[Port; 2]>>,
+    // The index of the port of this side, the other side has the other one
+    side: usize,
+}
+
+impl LinkCableEnd {
+    // Returns both ends of a new cable
+    pub fn pair() -> (LinkCableEnd, LinkCableEnd) {
+        let ports = Rc::new(RefCell::new([Port::default(), Port::default()]));
+        (
+            LinkCableEnd {
+                ports: Rc::clone(&ports),
+                side: 0,
+            },
+            LinkCableEnd { ports, side: 1 },
+        )
+    }
+}
+
+impl SerialDevice for LinkCableEnd {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut ports = self.ports.borrow_mut();
+        let other = &mut ports[1 - self.side];
+        match other.waiting.take() {
+            Some(reply) => {
+                other.received = Some(byte);
+                reply
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn poll(&mut self, waiting: Option<u8>) -> Option<u8> {
+        let mut ports = self.ports.borrow_mut();
+        let port = &mut ports[self.side];
+        // A completed transfer no longer waits, even though SB still holds the byte sent
+        let received = port.received.take();
+        port.waiting = if received.is_some() { None } else { waiting };
+        received
+    }
+}
@@ -0,0 +1,11 @@
+mod device;
+mod link_cable;
+mod serial;
+#[cfg(unix)]
+mod socket_link;
+
+pub use device::{SerialCapture, SerialDevice, test_result};
+pub use link_cable::LinkCableEnd;
+pub use serial::Serial;
+#[cfg(unix)]
+pub use socket_link::SocketLink;
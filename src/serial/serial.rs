@@ -0,0 +1,189 @@
+/*!
+The serial port shifts the byte in SB (0xFF01) out to the link port while shifting the byte of the other
+side in, one bit per clock, after a write to SC (0xFF02) with bit 7 set. Bit 0 of SC selects whether the
+Game Boy drives the clock itself at 8192 Hz, or 262144 Hz if bit 1 is set on the CGB, or waits for the
+clock of the other side. The internal clock is derived from the system counter, so it is twice as fast in
+double speed mode. After 8 bits, bit 7 of SC is cleared and the Serial interrupt is requested. Without a
+partner, the internal clock reads in 0xFF, while a transfer with an external clock never completes.
+https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+*/
+
+use crate::interrupts::{Interrupt, InterruptFlags};
+use crate::model::Model;
+use crate::serial::SerialDevice;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const TRANSFER_ENABLE: u8 = 0x80;
+const FAST_CLOCK: u8 = 0x02;
+const INTERNAL_CLOCK: u8 = 0x01;
+
+const BITS_PER_TRANSFER: u8 = 8;
+
+// The bits of the system counter whose falling edge shifts one bit at 8192 Hz and 262144 Hz
+const NORMAL_CLOCK_BIT: u16 = 8;
+const FAST_CLOCK_BIT: u16 = 3;
+
+pub struct Serial {
+    interrupt_flags: Rc<RefCell<InterruptFlags>>,
+    model: Model,
+
+    sb: u8,
+    sc: u8,
+    // The bits shifted so far by the running transfer with the internal clock
+    bits: u8,
+    device: Option<Box<dyn SerialDevice>>,
+}
+
+impl Serial {
+    pub fn new(interrupt_flags: Rc<RefCell<InterruptFlags>>, model: Model) -> Serial {
+        Serial {
+            interrupt_flags,
+            model,
+            sb: 0,
+            sc: 0,
+            bits: 0,
+            device: None,
+        }
+    }
+
+    // The CGB boot ROM leaves the internal clock selected
+    pub fn skip_boot_rom(&mut self) {
+        if self.model == Model::CGB {
+            self.sc = INTERNAL_CLOCK;
+        }
+    }
+
+    // Connects a device to the link port, replacing the one connected before
+    pub fn connect(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = Some(device);
+    }
+
+    pub fn read_sb(&self) -> u8 {
+        self.sb
+    }
+
+    pub fn write_sb(&mut self, value: u8) {
+        self.sb = value;
+    }
+
+    // The unused bits read as 1
+    pub fn read_sc(&self) -> u8 {
+        !self.sc_mask() | self.sc
+    }
+
+    // Writing SC restarts the transfer
+    pub fn write_sc(&mut self, value: u8) {
+        self.sc = value & self.sc_mask();
+        self.bits = 0;
+    }
+
+    // The clock speed in bit 1 only exists on the CGB
+    fn sc_mask(&self) -> u8 {
+        match self.model {
+            Model::DMG => TRANSFER_ENABLE | INTERNAL_CLOCK,
+            Model::CGB => TRANSFER_ENABLE | FAST_CLOCK | INTERNAL_CLOCK,
+        }
+    }
+
+    // Shifts a bit on the falling edge of the internal clock, given the system counter before and after it
+    // changed
+    pub fn clock_counter(&mut self, old_counter: u16, counter: u16) {
+        if (self.sc & (TRANSFER_ENABLE | INTERNAL_CLOCK)) != (TRANSFER_ENABLE | INTERNAL_CLOCK) {
+            return;
+        }
+
+        let bit = if (self.sc & FAST_CLOCK) == FAST_CLOCK {
+            FAST_CLOCK_BIT
+        } else {
+            NORMAL_CLOCK_BIT
+        };
+        let falling_edge = (old_counter >> bit) & 0x01 == 0x01 && (counter >> bit) & 0x01 == 0x00;
+        if !falling_edge {
+            return;
+        }
+
+        self.bits += 1;
+        if self.bits == BITS_PER_TRANSFER {
+            self.sb = match self.device.as_mut() {
+                Some(device) => device.transfer(self.sb),
+                None => 0xFF,
+            };
+            self.complete_transfer();
+        }
+    }
+
+    // Lets the device complete a transfer the Game Boy waits for with an external clock
+    pub fn clock_cycle(&mut self) {
+        let Some(device) = self.device.as_mut() else {
+            return;
+        };
+
+        let waiting = (self.sc & (TRANSFER_ENABLE | INTERNAL_CLOCK)) == TRANSFER_ENABLE;
+        if let Some(byte) = device.poll(waiting.then_some(self.sb))
+            && waiting
+        {
+            self.sb = byte;
+            self.complete_transfer();
+        }
+    }
+
+    fn complete_transfer(&mut self) {
+        self.sc &= !TRANSFER_ENABLE;
+        self.bits = 0;
+        self.interrupt_flags.borrow_mut().request(Interrupt::Serial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::{SerialCapture, test_result};
+
+    // Sends the text with the internal clock at 8192 Hz, driven by the system counter like on the DMG
+    fn send(serial: &mut Serial, interrupt_flags: &Rc<RefCell<InterruptFlags>>, text: &str) {
+        let mut counter: u16 = 0;
+        for byte in text.bytes() {
+            serial.write_sb(byte);
+            serial.write_sc(TRANSFER_ENABLE | INTERNAL_CLOCK);
+            interrupt_flags.borrow_mut().write(0x00);
+
+            // One bit is shifted every 128 M-cycles
+            let mut cycles = 0;
+            while (serial.read_sc() & TRANSFER_ENABLE) != 0 {
+                serial.clock_counter(counter, counter.wrapping_add(4));
+                counter = counter.wrapping_add(4);
+                cycles += 1;
+            }
+            assert!(cycles <= 8 * 128);
+            assert_eq!(serial.read_sb(), 0xFF);
+            assert_ne!(
+                interrupt_flags.borrow().pending() & Interrupt::Serial.bit(),
+                0
+            );
+        }
+    }
+
+    #[test]
+    fn capture_internal_clock_transfers() {
+        let interrupt_flags = Rc::new(RefCell::new(InterruptFlags::new()));
+        let mut serial = Serial::new(Rc::clone(&interrupt_flags), Model::DMG);
+        let capture = SerialCapture::new();
+        let output = capture.output();
+        serial.connect(Box::new(capture));
+
+        send(&mut serial, &interrupt_flags, "cpu_instrs\n\n");
+        assert_eq!(*output.borrow(), "cpu_instrs\n\n");
+        assert_eq!(test_result(&output.borrow()), None);
+
+        send(&mut serial, &interrupt_flags, "Passed\n");
+        assert_eq!(*output.borrow(), "cpu_instrs\n\nPassed\n");
+        assert_eq!(test_result(&output.borrow()), Some(true));
+    }
+
+    #[test]
+    fn detect_failed_test_rom() {
+        assert_eq!(test_result("01-special\n\nFailed #2\n"), Some(false));
+        assert_eq!(test_result("01-special\n\nPass"), None);
+    }
+}
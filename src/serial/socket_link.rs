@@ -0,0 +1,217 @@
+/*!
+A link cable between two emulator processes on the same machine, over a Unix domain socket. One emulator
+listens on the socket path and the other one connects to it. The master sends its byte at the end of a
+transfer and waits for the reply, which the other emulator sends the next time it checks the socket: the
+byte in its SB if it waits for a transfer with an external clock, or 0xFF otherwise. If both emulators
+drive the clock at the same time, each of them takes the byte of the other one as the reply. A reply that
+does not arrive in time, because the other emulator is stopped or gone, reads as 0xFF like an unconnected
+port.
+https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+*/
+
+use crate::serial::SerialDevice;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+// Every message is a kind followed by a byte
+const MESSAGE_SIZE: usize = 2;
+const TRANSFER: u8 = 0x01;
+const REPLY: u8 = 0x02;
+
+// The M-cycles between two checks for a transfer of the other emulator, which waits for the reply meanwhile
+const POLL_INTERVAL: u32 = 64;
+
+// How long the master waits for the reply before it reads 0xFF
+const REPLY_TIMEOUT: Duration = Duration::from_millis(250);
+
+pub struct SocketLink {
+    // None once the other emulator disconnected, which leaves the port without a partner
+    stream: Option<UnixStream>,
+    nonblocking: bool,
+    // The bytes of an incomplete message
+    buffer: Vec<u8>,
+    cycles_since_poll: u32,
+    // The replies that arrived too late for their transfer, which are dropped when they come in
+    stale_replies: u32,
+}
+
+impl SocketLink {
+    // Waits for the other emulator to connect to the socket at the path. A socket left behind at the path
+    // is replaced, and the path is removed again once connected
+    pub fn listen<P: AsRef<Path>>(path: P) -> std::io::Result<SocketLink> {
+        let path = path.as_ref();
+        if let Ok(metadata) = std::fs::symlink_metadata(path)
+            && metadata.file_type().is_socket()
+        {
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let accepted = listener.accept();
+        std::fs::remove_file(path)?;
+        SocketLink::new(accepted?.0)
+    }
+
+    // Connects to the other emulator listening on the socket at the path
+    pub fn connect<P: AsRef<Path>>(path: P) -> std::io::Result<SocketLink> {
+        SocketLink::new(UnixStream::connect(path)?)
+    }
+
+    fn new(stream: UnixStream) -> std::io::Result<SocketLink> {
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        Ok(SocketLink {
+            stream: Some(stream),
+            nonblocking: false,
+            buffer: Vec::new(),
+            cycles_since_poll: 0,
+            stale_replies: 0,
+        })
+    }
+
+    fn send(&mut self, kind: u8, byte: u8) {
+        if let Some(stream) = self.stream.as_mut()
+            && let Err(error) = stream.write_all(&[kind, byte])
+        {
+            self.disconnect(error);
+        }
+    }
+
+    // Returns the next message of the other emulator, or None if there is none yet and nonblocking is set,
+    // or none arrived within the read timeout otherwise
+    fn receive(&mut self, nonblocking: bool) -> Option<(u8, u8)> {
+        loop {
+            if self.buffer.len() >= MESSAGE_SIZE {
+                let message = (self.buffer[0], self.buffer[1]);
+                self.buffer.drain(..MESSAGE_SIZE);
+                if message.0 == REPLY && self.stale_replies > 0 {
+                    self.stale_replies -= 1;
+                    continue;
+                }
+                return Some(message);
+            }
+
+            let stream = self.stream.as_mut()?;
+            if self.nonblocking != nonblocking {
+                if let Err(error) = stream.set_nonblocking(nonblocking) {
+                    self.disconnect(error);
+                    return None;
+                }
+                self.nonblocking = nonblocking;
+            }
+
+            let mut data = [0; 64];
+            match stream.read(&mut data) {
+                Ok(0) => {
+                    self.disconnect(ErrorKind::UnexpectedEof.into());
+                    return None;
+                }
+                Ok(length) => self.buffer.extend_from_slice(&data[..length]),
+                // A timeout shows up as WouldBlock on Unix
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    return None;
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => {
+                    self.disconnect(error);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn disconnect(&mut self, error: std::io::Error) {
+        if self.stream.take().is_some() {
+            println!("WARNING: The link cable was disconnected: {error}");
+        }
+    }
+}
+
+impl SerialDevice for SocketLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.send(TRANSFER, byte);
+        loop {
+            match self.receive(false) {
+                // The other emulator drives the clock as well
+                Some((REPLY | TRANSFER, reply)) => return reply,
+                Some(_) => {}
+                None => {
+                    // The reply may still come in later, and must not be taken for the next one
+                    if self.stream.is_some() {
+                        self.stale_replies += 1;
+                    }
+                    return 0xFF;
+                }
+            }
+        }
+    }
+
+    fn poll(&mut self, waiting: Option<u8>) -> Option<u8> {
+        self.cycles_since_poll += 1;
+        if self.cycles_since_poll < POLL_INTERVAL {
+            return None;
+        }
+        self.cycles_since_poll = 0;
+
+        while let Some((kind, byte)) = self.receive(true) {
+            if kind == TRANSFER {
+                self.send(REPLY, waiting.unwrap_or(0xFF));
+                if waiting.is_some() {
+                    return Some(byte);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (SocketLink, UnixStream) {
+        let (stream, other) = UnixStream::pair().unwrap();
+        (SocketLink::new(stream).unwrap(), other)
+    }
+
+    #[test]
+    fn transfer_without_reply_reads_ff() {
+        let (mut link, mut other) = pair();
+        assert_eq!(link.transfer(0x12), 0xFF);
+
+        let mut message = [0; MESSAGE_SIZE];
+        other.read_exact(&mut message).unwrap();
+        assert_eq!(message, [TRANSFER, 0x12]);
+    }
+
+    #[test]
+    fn transfer_after_disconnect_reads_ff() {
+        let (mut link, other) = pair();
+        drop(other);
+        assert_eq!(link.transfer(0x12), 0xFF);
+        assert!(link.stream.is_none());
+        assert_eq!(link.transfer(0x34), 0xFF);
+    }
+
+    #[test]
+    fn late_reply_is_dropped() {
+        let (mut link, mut other) = pair();
+        assert_eq!(link.transfer(0x12), 0xFF);
+
+        // The reply to the first transfer arrives after the timeout, before the reply to the second one
+        other.write_all(&[REPLY, 0x34, REPLY, 0x56]).unwrap();
+        assert_eq!(link.transfer(0x78), 0x56);
+        assert_eq!(link.stale_replies, 0);
+    }
+
+    #[test]
+    fn reply_is_received() {
+        let (mut link, mut other) = pair();
+        other.write_all(&[REPLY, 0x34]).unwrap();
+        assert_eq!(link.transfer(0x12), 0x34);
+    }
+}
@@ -0,0 +1,23 @@
+/*!
+The emulator runs until it is asked to quit with Ctrl+C (SIGINT) or by the system (SIGTERM). Instead of
+terminating the process right away, the signal handler only notes the request, so the main loop can
+return and the emulator can write the save file and finish a recording on its way out.
+https://docs.rs/signal-hook/latest/signal_hook/flag/index.html
+*/
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+
+static REQUESTED: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+
+pub fn install_handlers() -> std::io::Result<()> {
+    signal_hook::flag::register(SIGINT, REQUESTED.clone())?;
+    signal_hook::flag::register(SIGTERM, REQUESTED.clone())?;
+    Ok(())
+}
+
+// Whether the emulator was asked to quit
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
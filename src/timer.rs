@@ -0,0 +1,242 @@
+/*!
+The timer is built around a 16-bit system counter that is incremented every T-cycle. DIV exposes its upper
+8 bits. TIMA is incremented whenever the counter bit selected by TAC, masked by the enable bit of TAC,
+falls from 1 to 0. Since this falling edge detector also sees changes caused by writing DIV or TAC, those
+writes can increment TIMA as well. When TIMA overflows, it reads as 0 for one M-cycle before it is
+reloaded from TMA and the Timer interrupt is requested.
+https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+*/
+
+use crate::interrupts::{Interrupt, InterruptFlags};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const T_CYCLES_PER_M_CYCLE: usize = 4;
+
+pub struct Timer {
+    interrupt_flags: Rc<RefCell<InterruptFlags>>,
+
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+
+    // TIMA overflowed during the last M-cycle and is reloaded in the next one
+    overflowed: bool,
+    // TIMA was reloaded in the last M-cycle, writes to it are ignored in the meantime
+    reloaded: bool,
+}
+
+impl Timer {
+    pub fn new(interrupt_flags: Rc<RefCell<InterruptFlags>>) -> Timer {
+        Timer {
+            interrupt_flags,
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0xF8,
+            overflowed: false,
+            reloaded: false,
+        }
+    }
+
+    // Sets the system counter to the value it has when the boot ROM finishes
+    pub fn set_counter(&mut self, counter: u16) {
+        self.counter = counter;
+    }
+
+    pub fn clock_cycle(&mut self) {
+        self.reloaded = false;
+        if self.overflowed {
+            self.overflowed = false;
+            self.reloaded = true;
+            self.tima = self.tma;
+            self.interrupt_flags.borrow_mut().request(Interrupt::Timer);
+        }
+
+        for _ in 0..T_CYCLES_PER_M_CYCLE {
+            let old_bit = self.timer_bit();
+            self.counter = self.counter.wrapping_add(1);
+            self.detect_falling_edge(old_bit);
+        }
+    }
+
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    pub fn read_div(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
+    pub fn read_tima(&self) -> u8 {
+        self.tima
+    }
+
+    pub fn read_tma(&self) -> u8 {
+        self.tma
+    }
+
+    // The upper 5 bits are not connected and always read as 1
+    pub fn read_tac(&self) -> u8 {
+        self.tac
+    }
+
+    // Writing any value to DIV resets the whole system counter
+    pub fn write_div(&mut self) {
+        let old_bit = self.timer_bit();
+        self.counter = 0;
+        self.detect_falling_edge(old_bit);
+    }
+
+    pub fn write_tima(&mut self, value: u8) {
+        // Writing TIMA while it reads as 0 after an overflow cancels the reload and the interrupt, while
+        // writes in the cycle it is reloaded are overwritten by TMA
+        if !self.reloaded {
+            self.tima = value;
+            self.overflowed = false;
+        }
+    }
+
+    pub fn write_tma(&mut self, value: u8) {
+        self.tma = value;
+        if self.reloaded {
+            self.tima = value;
+        }
+    }
+
+    pub fn write_tac(&mut self, value: u8) {
+        let old_bit = self.timer_bit();
+        self.tac = value | 0xF8;
+        self.detect_falling_edge(old_bit);
+    }
+
+    // The counter bit selected by TAC, masked by the timer enable bit
+    fn timer_bit(&self) -> bool {
+        let bit = match self.tac & 0x03 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        let enabled = (self.tac & 0x04) == 0x04;
+
+        enabled && ((self.counter >> bit) & 0x01) == 0x01
+    }
+
+    fn detect_falling_edge(&mut self, old_bit: bool) {
+        if old_bit && !self.timer_bit() {
+            let (tima, overflowed) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            if overflowed {
+                self.overflowed = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A timer incrementing TIMA every 4 M-cycles, when bit 3 of the counter falls
+    fn timer() -> (Timer, Rc<RefCell<InterruptFlags>>) {
+        let interrupt_flags = Rc::new(RefCell::new(InterruptFlags::new()));
+        let mut timer = Timer::new(Rc::clone(&interrupt_flags));
+        timer.write_tac(0x05);
+        (timer, interrupt_flags)
+    }
+
+    fn timer_requested(interrupt_flags: &Rc<RefCell<InterruptFlags>>) -> bool {
+        (interrupt_flags.borrow().pending() & Interrupt::Timer.bit()) != 0
+    }
+
+    // Clocks the timer until TIMA overflowed from 0xFF and reads as 0
+    fn overflow(timer: &mut Timer) {
+        timer.write_tma(0x23);
+        timer.write_tima(0xFF);
+        for _ in 0..4 {
+            timer.clock_cycle();
+        }
+        assert_eq!(timer.read_tima(), 0x00);
+    }
+
+    #[test]
+    fn overflow_reloads_one_cycle_later() {
+        let (mut timer, interrupt_flags) = timer();
+        timer.write_tma(0x23);
+        timer.write_tima(0xFF);
+
+        for _ in 0..3 {
+            timer.clock_cycle();
+        }
+        assert_eq!(timer.read_tima(), 0xFF);
+
+        timer.clock_cycle();
+        assert_eq!(timer.read_tima(), 0x00);
+        assert!(!timer_requested(&interrupt_flags));
+
+        timer.clock_cycle();
+        assert_eq!(timer.read_tima(), 0x23);
+        assert!(timer_requested(&interrupt_flags));
+    }
+
+    #[test]
+    fn tima_write_before_reload_cancels_it() {
+        let (mut timer, interrupt_flags) = timer();
+        overflow(&mut timer);
+
+        timer.write_tima(0x50);
+        timer.clock_cycle();
+        assert_eq!(timer.read_tima(), 0x50);
+        assert!(!timer_requested(&interrupt_flags));
+    }
+
+    #[test]
+    fn tima_write_during_reload_is_ignored() {
+        let (mut timer, interrupt_flags) = timer();
+        overflow(&mut timer);
+        timer.clock_cycle();
+
+        timer.write_tima(0x50);
+        assert_eq!(timer.read_tima(), 0x23);
+        assert!(timer_requested(&interrupt_flags));
+
+        // TMA is still copied into TIMA in this cycle
+        timer.write_tma(0x42);
+        assert_eq!(timer.read_tima(), 0x42);
+    }
+
+    #[test]
+    fn div_reset_increments_on_falling_edge() {
+        let (mut timer, _) = timer();
+        timer.set_counter(0x0008);
+        timer.write_div();
+        assert_eq!(timer.read_tima(), 0x01);
+        assert_eq!(timer.read_div(), 0x00);
+
+        // Without the selected bit set, resetting the counter has no effect on TIMA
+        timer.set_counter(0x0010);
+        timer.write_div();
+        assert_eq!(timer.read_tima(), 0x01);
+    }
+
+    #[test]
+    fn tac_change_increments_on_falling_edge() {
+        let (mut timer, _) = timer();
+        timer.set_counter(0x0008);
+
+        // Selecting bit 9 instead of bit 3, which is set
+        timer.write_tac(0x04);
+        assert_eq!(timer.read_tima(), 0x01);
+
+        // Disabling the timer while the selected bit is set
+        timer.write_tac(0x05);
+        timer.write_tac(0x01);
+        assert_eq!(timer.read_tima(), 0x02);
+
+        // Selecting a bit that is set does not increment
+        timer.write_tac(0x05);
+        assert_eq!(timer.read_tima(), 0x02);
+    }
+}